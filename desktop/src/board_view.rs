@@ -0,0 +1,69 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// a picture of the board: every header pin where it really sits, with dojocore's pins colored like the pin grid
+use crate::pin_color;
+use dojolib::board::PinData;
+use dojolib::profiles::{self, PIN_PITCH, Profile};
+use eframe::egui::{self, Color32, Rect, Sense, Stroke, StrokeKind, Vec2};
+
+const SOLDER_MASK: Color32 = Color32::from_rgb(0, 110, 130);
+const PAD: Color32 = Color32::from_gray(40); // header pins dojocore doesn't drive (GND, 5V, ...)
+
+pub fn show<'a>(ui: &mut egui::Ui, profile: &Profile, pins: impl Iterator<Item = &'a PinData>) {
+    ui.label(profile.name);
+    let pins: Vec<&PinData> = pins.collect();
+    let width = ui.available_width();
+    let scale = width / profile.size.0; // pixels per millimeter
+    let (res, paint) =
+        ui.allocate_painter(Vec2::new(width, profile.size.1 * scale), Sense::hover());
+    paint.rect_filled(res.rect, 2.0 * scale, SOLDER_MASK);
+    let pointer = res.hover_pos();
+    let mut hovered = None;
+    for header in profile.headers {
+        for (i, label) in header.pins.iter().enumerate() {
+            let offset = i as f32 * PIN_PITCH;
+            let (x, y) = if header.vertical {
+                (header.x, header.y + offset)
+            } else {
+                (header.x + offset, header.y)
+            };
+            let pad = Rect::from_center_size(
+                res.rect.min + Vec2::new(x, y) * scale,
+                Vec2::splat(PIN_PITCH * scale * 0.8),
+            );
+            let pin = pins
+                .iter()
+                .find(|pin| profiles::silkscreen(&pin.ident) == *label);
+            let fill = match pin {
+                Some(pin) => pin_color(pin, profile.adc_max()).into(),
+                None => PAD,
+            };
+            paint.rect_filled(pad, 0.0, fill);
+            paint.rect_stroke(
+                pad,
+                0.0,
+                Stroke::new(1.0, Color32::BLACK),
+                StrokeKind::Inside,
+            );
+            if pointer.is_some_and(|pointer| pad.contains(pointer)) {
+                hovered = Some(match pin {
+                    Some(pin) => format!("{} ({})", pin.name(), label),
+                    None => label.to_string(),
+                });
+            }
+        }
+    }
+    if let Some(text) = hovered {
+        res.on_hover_text(text);
+    }
+}
@@ -11,100 +11,474 @@ THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS”
 
 */
 use dojolib::{
-    Board,
-    board::{PinMode, PinStatus},
+    Ack, Board, CircuitDojoError, Layout, PortInfo, Recorder,
+    board::{PinData, PinMode, PinStatus, PinType},
+    profiles::{self, Profile},
+    sim::{SimBoard, Wire},
 };
-use eframe::egui::{self, Align2, Color32, FontId, Rect, Rgba, Sense, Stroke, StrokeKind};
+use eframe::egui::{self, Align2, Color32, FontId, Rgba, RichText, Sense, Stroke, StrokeKind};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+mod board_view;
+mod scope;
+mod settings;
+mod timeline;
+use scope::{Scope, Sweep};
+use timeline::Timeline;
 
 trait Screen {
     fn draw(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) -> Option<Box<dyn Screen>>; // draw function that meshes nicely with egui
     // and optionally passes control to a different Screen
 }
 
+const DEMO_BOARD: &str = "Demo board"; // always offered at the bottom of the port list
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1); // how often the port list looks for boards coming and going
+
 struct PortPickerScreen {
-    ports_list: Vec<String>,
-    selected: usize,
+    ports: Vec<PortInfo>,
+    selected: Option<String>,  // port name; None is the demo board
+    chosen: bool, // the user picked the selection, so leave it alone; otherwise it follows boards being plugged in
+    last_port: Option<String>, // where we connected last time
+    listing: Receiver<dojolib::Result<Vec<PortInfo>>>, // the port list, looked up every REFRESH_INTERVAL
+    status: String,                                    // why the last Start didn't work
+    checking: Option<Receiver<dojolib::Result<String>>>, // a Check that hasn't finished yet
+    found: String,                                     // what the last Check turned up
+    connecting: Option<(String, Receiver<dojolib::Result<Board>>)>, // a Start that hasn't finished yet
 }
 
 impl PortPickerScreen {
-    fn new() -> Self {
+    fn new(ctx: &egui::Context) -> Self {
+        // looking the ports up can take a while on some systems, so it's done on a thread, which wakes the picker up
+        // with each new list and stops when the picker goes away
+        let (tx, listing) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            while tx.send(dojolib::ports()).is_ok() {
+                ctx.request_repaint();
+                std::thread::sleep(REFRESH_INTERVAL);
+            }
+        });
         Self {
-            ports_list: dojolib::ports().unwrap(),
-            selected: 0,
+            ports: vec![],
+            selected: None,
+            chosen: false,
+            last_port: settings::last_port(),
+            listing,
+            status: String::new(),
+            checking: None,
+            found: String::new(),
+            connecting: None,
+        }
+    }
+
+    fn refresh(&mut self, ports: Vec<PortInfo>) {
+        self.ports = ports;
+        if self.selected.is_some() && self.port().is_none() {
+            // it got unplugged
+            self.chosen = false;
+            self.found.clear();
+        }
+        if !self.chosen {
+            // the last port we used if it's here, otherwise the likeliest board, otherwise the demo
+            self.selected = self
+                .ports
+                .iter()
+                .find(|port| Some(&port.name) == self.last_port.as_ref())
+                .or_else(|| self.ports.iter().find(|port| port.looks_like_arduino()))
+                .map(|port| port.name.clone());
+        }
+    }
+
+    fn port(&self) -> Option<&PortInfo> {
+        let selected = self.selected.as_ref()?;
+        self.ports.iter().find(|port| &port.name == selected)
+    }
+
+    fn label(port: Option<&PortInfo>) -> RichText {
+        match port {
+            Some(port) if port.looks_like_arduino() => RichText::new(port.to_string()).strong(),
+            Some(port) => RichText::new(port.to_string()),
+            None => RichText::new(DEMO_BOARD),
         }
     }
+
+    fn check(&mut self, port: PortInfo) {
+        // probing takes seconds when nothing answers, so it gets a thread
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(port.probe(115200));
+        });
+        self.checking = Some(rx);
+        self.found = "Checking...".to_string();
+    }
+
+    fn connect(&mut self, port: Option<PortInfo>) {
+        // the handshake can take a few seconds, so it gets a thread too. if it's cancelled, the board is dropped
+        // as soon as the thread finds nobody's listening anymore
+        let (tx, rx) = mpsc::channel();
+        let name = match port {
+            Some(port) => {
+                let name = port.name.clone();
+                std::thread::spawn(move || {
+                    let _ = tx.send(Board::open(&port.name, 115200));
+                });
+                name
+            }
+            None => {
+                std::thread::spawn(move || {
+                    // wired up like the screenshot in the README, plus something to measure on A0
+                    let _ = tx.send(Board::new(
+                        SimBoard::uno()
+                            .wire(Wire::Tie(5, 7))
+                            .wire(Wire::Analog(14, 512))
+                            .spawn(),
+                    ));
+                });
+                DEMO_BOARD.to_string()
+            }
+        };
+        self.connecting = Some((name, rx));
+        self.status.clear();
+    }
+
+    fn connected(
+        &mut self,
+        name: String,
+        board: dojolib::Result<Board>,
+    ) -> Option<Box<dyn Screen>> {
+        match board {
+            Ok(board) => {
+                if name != DEMO_BOARD {
+                    settings::remember_port(&name);
+                }
+                return Some(Box::new(MainScreen::new(board)));
+            }
+            Err(CircuitDojoError::IncompatibleFirmware(why)) => {
+                self.status = format!(
+                    "This board's dojocore is out of date ({}). Re-upload it from the Arduino IDE.",
+                    why
+                )
+            }
+            Err(e) => self.status = format!("Couldn't connect to {}: {:?}", name, e),
+        }
+        None
+    }
 }
 
 impl Screen for PortPickerScreen {
-    fn draw(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) -> Option<Box<dyn Screen>> {
+    fn draw(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) -> Option<Box<dyn Screen>> {
         let mut rtval: Option<Box<dyn Screen>> = None;
+        // if the list can't be read this time, keep showing the last one
+        if let Some(ports) = self.listing.try_iter().flatten().last() {
+            self.refresh(ports);
+        }
+        if let Some(checking) = &self.checking {
+            match checking.try_recv() {
+                Ok(result) => {
+                    self.found = match result {
+                        Ok(name) => format!("Found {}", name),
+                        Err(e) => format!("No dojocore answered ({:?})", e),
+                    };
+                    self.checking = None;
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Disconnected) => self.checking = None,
+            }
+        }
+        if let Some((_, connecting)) = &self.connecting {
+            match connecting.try_recv() {
+                Ok(board) => {
+                    let (name, _) = self.connecting.take().unwrap();
+                    rtval = self.connected(name, board);
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Disconnected) => self.connecting = None,
+            }
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
+            let previous = self.selected.clone();
             egui::ComboBox::from_label("Select A Port")
-                .selected_text(self.ports_list[self.selected].clone())
+                .selected_text(Self::label(self.port()))
                 .show_ui(ui, |ui| {
-                    for (i, port) in self.ports_list.iter().enumerate() {
-                        ui.selectable_value(&mut self.selected, i, port);
+                    for port in &self.ports {
+                        ui.selectable_value(
+                            &mut self.selected,
+                            Some(port.name.clone()),
+                            Self::label(Some(port)),
+                        );
                     }
+                    ui.selectable_value(&mut self.selected, None, Self::label(None));
+                });
+            if self.selected != previous {
+                self.chosen = true;
+                self.found.clear();
+            }
+            let port = self.port().cloned();
+            if self.ports.is_empty() {
+                ui.label("No serial ports found. Plug your board in and it'll show up here, or try the demo board.");
+            } else if let Some(port) = &port
+                && port.looks_like_arduino()
+            {
+                match port.profile() {
+                    Some(profile) => ui.label(format!("This looks like an {}.", profile.name)),
+                    None => ui.label("This looks like a board."),
+                };
+            }
+            if let Some((name, _)) = &self.connecting {
+                let mut cancel = false;
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Connecting to {}...", name));
+                    cancel = ui.button("Cancel").clicked();
                 });
-            if ui.button("Start").clicked() {
-                rtval = Some(Box::new(MainScreen::new(&self.ports_list[self.selected])));
+                if cancel {
+                    self.connecting = None;
+                }
+                return;
             }
+            ui.horizontal(|ui| {
+                // the port is busy while it's being checked
+                let idle = self.checking.is_none();
+                if ui.add_enabled(idle, egui::Button::new("Start")).clicked() {
+                    self.connect(port.clone());
+                }
+                if let Some(port) = port
+                    && ui
+                        .add_enabled(idle, egui::Button::new("Check"))
+                        .on_hover_text("See if dojocore is running on this port")
+                        .clicked()
+                {
+                    self.check(port);
+                }
+                ui.label(&self.found);
+            });
+            ui.colored_label(Color32::RED, &self.status);
         });
         rtval
     }
 }
 
+const RECORDING_CAPACITY: usize = 1_000_000;
+
+fn pin_color(pin: &PinData, adc_max: u16) -> Rgba {
+    // grey when off, otherwise red for low and green for high, fading between them for analog
+    match (pin.mode, pin.status) {
+        (PinMode::Unset, _) => Rgba::from_rgb(0.7, 0.7, 0.7),
+        (_, PinStatus::AnalogInputting(val)) => {
+            let level = val as f32 / adc_max as f32;
+            Rgba::from_rgb(1.0 - level, level, 0.0)
+        }
+        (_, PinStatus::AnalogOutputting(duty)) => {
+            let level = duty as f32 / 255.0;
+            Rgba::from_rgb(1.0 - level, level, 0.0)
+        }
+        (
+            _,
+            PinStatus::DigitalOutputting(true)
+            | PinStatus::DigitalInputting(true)
+            | PinStatus::DigitalPullupInputting(true),
+        ) => Rgba::from_rgb(0.0, 1.0, 0.0),
+        _ => Rgba::from_rgb(1.0, 0.0, 0.0),
+    }
+}
+
 struct MainScreen {
     board: Board,
+    layout_path: String,
+    layout_status: String, // what happened the last time we tried to load a layout
+    recording: Option<Recorder>, // the last finished recording, waiting to be saved
+    save_path: String,
+    save_status: String,
+    timeline: Timeline,
+    scope: Scope,
+    disconnected: Option<CircuitDojoError>, // why the board went away, if it did
+    pending: Vec<Ack>,                      // clicks the board hasn't answered yet
+    refused: String,                        // the last one it said no to
+    profile: &'static Profile, // for scaling readings; dojocore's own default if we don't know the board
 }
 
 impl MainScreen {
-    fn new(port: impl AsRef<str>) -> Self {
-        let mut board = Board::new(port, 115200).unwrap();
+    fn new(mut board: Board) -> Self {
         board.subscribe(16).unwrap(); // 16ms sample rate = 60hz
-        Self { board }
+        if board.capabilities().timestamps {
+            board.set_timestamps(true).unwrap(); // so edges on the timeline land where they happened, not when we read them
+        }
+        board.start_recording(RECORDING_CAPACITY); // always recording, so the timeline has something to show
+        let profile = board.profile().unwrap_or(&profiles::UNO_R3);
+        Self {
+            board,
+            layout_path: String::new(),
+            layout_status: String::new(),
+            recording: None,
+            save_path: "recording.csv".to_string(),
+            save_status: String::new(),
+            timeline: Timeline::new(profile.adc_max()),
+            scope: Scope::new(),
+            disconnected: None,
+            pending: vec![],
+            refused: String::new(),
+            profile,
+        }
+    }
+
+    fn check(&mut self, result: dojolib::Result<()>) {
+        // NotConnected just means the worker stopped; the next update() returns the error that says why
+        if let Err(e) = result
+            && !matches!(e, CircuitDojoError::NotConnected)
+            && self.disconnected.is_none()
+        {
+            self.disconnected = Some(e);
+        }
+    }
+
+    fn sent(&mut self, ack: dojolib::Result<Ack>) {
+        match ack {
+            Ok(ack) => self.pending.push(ack),
+            Err(e) => self.check(Err(e)),
+        }
+    }
+
+    fn load_layout(&mut self) {
+        self.layout_status = match Layout::load(&self.layout_path)
+            .and_then(|layout| self.board.apply_layout(&layout).map(|_| layout))
+        {
+            Ok(layout) => format!("Loaded {}", layout.name.as_deref().unwrap_or("layout")),
+            Err(e) => format!("Couldn't load layout: {:?}", e),
+        };
     }
 }
 
 impl Screen for MainScreen {
-    fn draw(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) -> Option<Box<dyn Screen>> {
-        self.board.update().unwrap();
+    fn draw(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) -> Option<Box<dyn Screen>> {
+        if self.disconnected.is_none() {
+            let result = self.board.update();
+            self.check(result);
+        }
+        self.pending.retain_mut(|ack| match ack.try_wait() {
+            None => true,
+            Some(result) => {
+                // NotConnected here means it got lost in a reconnect, and the board's been put back the way it was since
+                if let Err(CircuitDojoError::BoardError(command)) = result {
+                    self.refused = format!("Board refused {:?}", command);
+                }
+                false
+            }
+        });
+        if let Some(e) = &self.disconnected {
+            // the recording is still here, so leave everything else up and let them save it before reconnecting
+            let mut reconnect = false;
+            egui::TopBottomPanel::top("disconnected").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        Color32::RED,
+                        format!("Lost connection to {}: {:?}", self.board.get_name(), e),
+                    );
+                    reconnect = ui.button("Reconnect").clicked();
+                });
+            });
+            if reconnect {
+                return Some(Box::new(PortPickerScreen::new(ctx)));
+            }
+        } else if !self.board.is_connected() {
+            egui::TopBottomPanel::top("reconnecting").show(ctx, |ui| {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!("Lost {}, reconnecting...", self.board.get_name()),
+                );
+            });
+        }
+        egui::TopBottomPanel::top("layout").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Layout file:");
+                ui.text_edit_singleline(&mut self.layout_path);
+                if ui.button("Load").clicked() {
+                    self.load_layout();
+                }
+                ui.label(&self.layout_status);
+                ui.colored_label(Color32::RED, &self.refused);
+            });
+            ui.horizontal(|ui| {
+                if let Some(recorder) = self.board.recorder() {
+                    ui.label(format!("Recording: {} samples", recorder.len()));
+                    if ui.button("Stop").clicked() {
+                        self.recording = self.board.stop_recording();
+                    }
+                } else if ui.button("Record").clicked() {
+                    self.board.start_recording(RECORDING_CAPACITY);
+                    self.save_status.clear();
+                }
+                ui.label("Save as (.csv, .jsonl or .vcd):");
+                ui.text_edit_singleline(&mut self.save_path);
+                if ui.button("Save").clicked() {
+                    self.save_status = match self.board.recorder().or(self.recording.as_ref()) {
+                        Some(recorder) => match recorder.save(&self.save_path) {
+                            Ok(()) => format!("Saved {} samples", recorder.len()),
+                            Err(e) => format!("Couldn't save: {:?}", e),
+                        },
+                        None => "Nothing recorded yet".to_string(),
+                    };
+                }
+                ui.label(&self.save_status);
+            });
+        });
+        egui::TopBottomPanel::bottom("timeline")
+            .resizable(true)
+            .show(ctx, |ui| {
+                // triggers only make sense on live data; a stopped recording just gets looked at
+                match self.scope.show(ui, self.board.recorder()) {
+                    Some(Sweep::Triggered { start, end, at }) => {
+                        self.timeline.frame(start, end, at)
+                    }
+                    Some(Sweep::FreeRun) => self.timeline.follow(),
+                    None => (),
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| match self.board.recorder() {
+                    Some(recorder) => self.timeline.show(ui, Some(recorder), true),
+                    None => self.timeline.show(ui, self.recording.as_ref(), false),
+                });
+            });
+        if let Some(profile) = self.board.profile() {
+            egui::SidePanel::right("board")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    board_view::show(ui, profile, self.board.pins());
+                });
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal_wrapped(|ui| {
                 let mut mode_op = None;
                 let mut out_op = None;
                 for pin in self.board.pins() {
                     let (res, paint) = ui.allocate_painter(egui::Vec2::splat(64.0), Sense::click());
-                    paint.rect_filled(
-                        res.rect,
-                        0.0,
-                        match pin.mode {
-                            PinMode::Unset => Rgba::from_rgb(0.7, 0.7, 0.7),
-                            _ => {
-                                if match pin.status {
-                                    PinStatus::DigitalOutputting(val) => val,
-                                    PinStatus::DigitalInputting(val) => val,
-                                    _ => false,
-                                } {
-                                    Rgba::from_rgb(0.0, 1.0, 0.0)
-                                } else {
-                                    Rgba::from_rgb(1.0, 0.0, 0.0)
-                                }
-                            }
-                        },
-                    );
+                    paint.rect_filled(res.rect, 0.0, pin_color(pin, self.profile.adc_max()));
                     paint.text(
                         res.rect.center_top(),
                         Align2::CENTER_TOP,
-                        &pin.ident,
+                        pin.name(),
                         FontId::monospace(8.0),
                         Color32::BLACK,
                     );
+                    if let PinStatus::AnalogInputting(val) = pin.status {
+                        paint.text(
+                            res.rect.center(),
+                            Align2::CENTER_CENTER,
+                            format!("{:.2}V", self.profile.volts(val)),
+                            FontId::monospace(10.0),
+                            Color32::BLACK,
+                        );
+                    }
                     let mode_rect = res.rect.split_top_bottom_at_fraction(0.7).1.shrink(1.0);
                     paint.rect_stroke(
                         mode_rect,
                         0.0,
-                        Stroke::new(2.0, Rgba::BLACK),
+                        if pin.locked {
+                            // locked pins get a faded box, since clicking it won't do anything
+                            Stroke::new(2.0, Rgba::from_rgb(0.4, 0.4, 0.4))
+                        } else {
+                            Stroke::new(2.0, Rgba::BLACK)
+                        },
                         StrokeKind::Inside,
                     );
                     paint.text(
@@ -112,40 +486,47 @@ impl Screen for MainScreen {
                         Align2::CENTER_CENTER,
                         match pin.mode {
                             PinMode::Input => "INPUT",
+                            PinMode::InputPullup => "PULLUP",
                             PinMode::Output => "OUTPUT",
                             PinMode::Unset => "OFF",
                         },
                         FontId::monospace(10.0),
                         Color32::BLACK,
                     );
-                    if res.clicked() {
-                        if let Some(pointer) = res.interact_pointer_pos() {
-                            if mode_rect.contains(pointer) {
-                                mode_op = Some((pin.mode, pin.hw_id));
+                    if res.clicked()
+                        && let Some(pointer) = res.interact_pointer_pos()
+                    {
+                        if mode_rect.contains(pointer) {
+                            if !pin.locked {
+                                mode_op = Some((pin.mode, pin.tp, pin.hw_id));
+                            }
+                        } else if let PinMode::Output = pin.mode {
+                            if let PinStatus::DigitalOutputting(val) = pin.status {
+                                out_op = Some((!val, pin.hw_id));
                             } else {
-                                if let PinMode::Output = pin.mode {
-                                    if let PinStatus::DigitalOutputting(val) = pin.status {
-                                        out_op = Some((!val, pin.hw_id));
-                                    } else {
-                                        out_op = Some((true, pin.hw_id));
-                                    }
-                                }
+                                out_op = Some((true, pin.hw_id));
                             }
                         }
                     }
                 }
-                if let Some((mode, id)) = mode_op {
-                    match mode {
-                        PinMode::Input => {
-                            self.board.set_output(id).unwrap();
+                if let Some((mode, tp, id)) = mode_op {
+                    let ack = match (mode, tp) {
+                        // clicking cycles input -> pullup (if the pin has one) -> output -> input
+                        (PinMode::Input, PinType::DigitalPullup) => {
+                            Some(self.board.set_input_pullup(id))
                         }
-                        PinMode::Output | PinMode::Unset => {
-                            self.board.set_input(id).unwrap();
+                        (PinMode::Input | PinMode::InputPullup, _) => {
+                            Some(self.board.set_output(id))
                         }
+                        (PinMode::Output | PinMode::Unset, _) => Some(self.board.set_input(id)),
+                    };
+                    if let Some(ack) = ack {
+                        self.sent(ack);
                     }
                 }
                 if let Some((status, id)) = out_op {
-                    self.board.digital_write(id, status).unwrap();
+                    let ack = self.board.digital_write(id, status);
+                    self.sent(ack);
                 }
             });
         });
@@ -161,7 +542,7 @@ struct CircuitDojoDesktop {
 impl CircuitDojoDesktop {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
         Self {
-            screen: Box::new(PortPickerScreen::new()),
+            screen: Box::new(PortPickerScreen::new(&cc.egui_ctx)),
         }
     }
 }
@@ -0,0 +1,311 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// scope-style triggering for the timeline: watches the recording for a trigger condition and frames the timeline around
+// it when it fires, with the same single / normal / auto modes as a bench scope
+use dojolib::{
+    Condition, Edge, Recorder, Trigger,
+    trigger::{MAX_PATTERN_PINS, Watch},
+};
+use eframe::egui;
+use std::time::{Duration, Instant};
+
+const AUTO_TIMEOUT: Duration = Duration::from_secs(2); // how long auto waits for a trigger before going back to live data
+const MARGIN: f64 = 0.05; // how much room to leave either side of a triggered sweep, as a fraction of it
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Off,
+    Auto, // show each trigger as it happens, and live data whenever there hasn't been one lately
+    Normal, // show each trigger as it happens, and hold the last one until the next
+    Single, // show the first trigger after Arm is clicked, then stop
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Edge,
+    Pattern,
+    Pulse,
+    Threshold,
+}
+
+// what the timeline should do after this frame
+pub enum Sweep {
+    Triggered { start: f64, end: f64, at: f64 }, // show start to end (seconds since recording start), marking the trigger at at
+    FreeRun, // nothing triggered in a while, so go back to following live data
+}
+
+pub struct Scope {
+    mode: Mode,
+    kind: Kind,
+    pin: u8,
+    edge: Edge,
+    level: bool,    // Pulse: the level of the pulse
+    pulse_ms: f64,  // Pulse: how long it has to last
+    threshold: u16, // Threshold: the raw reading to cross
+    pattern: Vec<(u8, bool)>,
+    pre: usize,
+    post: usize,
+    armed: bool,
+    watch: Option<Watch>, // None until the settings are used, and again whenever they change
+    recording: Option<Instant>, // when the recording we're watching started, to notice it being replaced
+    scanned: u64,               // recorder total() as of the last sample fed to the watch
+    fired: Option<u64>, // the sample that fired the trigger, if we're waiting for the samples after it
+    last_sweep: Instant,
+    free_running: bool,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Off,
+            kind: Kind::Edge,
+            pin: 0,
+            edge: Edge::Rising,
+            level: true,
+            pulse_ms: 100.0,
+            threshold: 512,
+            pattern: vec![],
+            pre: 20,
+            post: 20,
+            armed: false,
+            watch: None,
+            recording: None,
+            scanned: 0,
+            fired: None,
+            last_sweep: Instant::now(),
+            free_running: true,
+        }
+    }
+
+    fn trigger(&self) -> Trigger {
+        let condition = match self.kind {
+            Kind::Edge => Condition::Edge(self.pin, self.edge),
+            Kind::Pattern => Condition::Pattern(self.pattern.clone()),
+            Kind::Pulse => Condition::PulseLonger(
+                self.pin,
+                self.level,
+                Duration::from_secs_f64(self.pulse_ms.max(0.0) / 1000.0),
+            ),
+            Kind::Threshold => Condition::Threshold(self.pin, self.threshold, self.edge),
+        };
+        Trigger {
+            pre: self.pre,
+            post: self.post,
+            ..Trigger::new(condition)
+        }
+    }
+
+    fn rearm(&mut self) {
+        // throws away whatever the watch has seen, so a change of settings starts from scratch
+        self.watch = None;
+        self.fired = None;
+        self.last_sweep = Instant::now();
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, recorder: Option<&Recorder>) -> Option<Sweep> {
+        let mut changed = false;
+        let pins: Vec<(u8, String)> = recorder
+            .map(|recorder| {
+                recorder
+                    .pins()
+                    .iter()
+                    .map(|pin| (pin.hw_id, pin.name().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let name = |hw_id: u8| {
+            pins.iter()
+                .find(|(pin, _)| *pin == hw_id)
+                .map_or_else(|| format!("Pin {}", hw_id), |(_, name)| name.clone())
+        };
+        ui.horizontal(|ui| {
+            ui.label("Trigger:");
+            for (mode, text) in [
+                (Mode::Off, "Off"),
+                (Mode::Auto, "Auto"),
+                (Mode::Normal, "Normal"),
+                (Mode::Single, "Single"),
+            ] {
+                if ui.selectable_value(&mut self.mode, mode, text).changed() {
+                    // single waits for Arm; the others are armed all the time
+                    self.armed = mode != Mode::Single;
+                    changed = true;
+                }
+            }
+            if self.mode == Mode::Single {
+                if ui
+                    .add_enabled(!self.armed, egui::Button::new("Arm"))
+                    .clicked()
+                {
+                    self.armed = true;
+                    changed = true;
+                }
+                ui.label(if self.armed { "Waiting..." } else { "Stopped" });
+            }
+            if self.mode == Mode::Off {
+                return;
+            }
+            ui.separator();
+            egui::ComboBox::from_id_salt("trigger kind")
+                .selected_text(format!("{:?}", self.kind))
+                .show_ui(ui, |ui| {
+                    for (kind, text) in [
+                        (Kind::Edge, "Edge"),
+                        (Kind::Pattern, "Pattern"),
+                        (Kind::Pulse, "Pulse longer than"),
+                        (Kind::Threshold, "Analog threshold"),
+                    ] {
+                        changed |= ui.selectable_value(&mut self.kind, kind, text).changed();
+                    }
+                });
+            if self.kind != Kind::Pattern {
+                egui::ComboBox::from_id_salt("trigger pin")
+                    .selected_text(name(self.pin))
+                    .show_ui(ui, |ui| {
+                        for (hw_id, pin_name) in &pins {
+                            changed |= ui
+                                .selectable_value(&mut self.pin, *hw_id, pin_name)
+                                .changed();
+                        }
+                    });
+            }
+            match self.kind {
+                Kind::Edge | Kind::Threshold => {
+                    if self.kind == Kind::Threshold {
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut self.threshold).prefix("at "))
+                            .changed();
+                    }
+                    egui::ComboBox::from_id_salt("trigger edge")
+                        .selected_text(format!("{:?}", self.edge))
+                        .show_ui(ui, |ui| {
+                            for edge in [Edge::Rising, Edge::Falling, Edge::Either] {
+                                changed |= ui
+                                    .selectable_value(&mut self.edge, edge, format!("{:?}", edge))
+                                    .changed();
+                            }
+                        });
+                }
+                Kind::Pulse => {
+                    changed |= ui.checkbox(&mut self.level, "high").changed();
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.pulse_ms)
+                                .range(0.0..=60_000.0)
+                                .suffix(" ms"),
+                        )
+                        .changed();
+                }
+                Kind::Pattern => {
+                    // each pin in the pattern gets a checkbox for the level it has to be at
+                    let mut remove = None;
+                    for (i, (pin, level)) in self.pattern.iter_mut().enumerate() {
+                        changed |= ui.checkbox(level, name(*pin)).changed();
+                        if ui.small_button("x").clicked() {
+                            remove = Some(i);
+                        }
+                    }
+                    if let Some(i) = remove {
+                        self.pattern.remove(i);
+                        changed = true;
+                    }
+                    if self.pattern.len() < MAX_PATTERN_PINS {
+                        egui::ComboBox::from_id_salt("trigger pattern")
+                            .selected_text("Add pin")
+                            .show_ui(ui, |ui| {
+                                for (hw_id, pin_name) in &pins {
+                                    if !self.pattern.iter().any(|(pin, _)| pin == hw_id)
+                                        && ui.selectable_label(false, pin_name).clicked()
+                                    {
+                                        self.pattern.push((*hw_id, true));
+                                        changed = true;
+                                    }
+                                }
+                            });
+                    }
+                }
+            }
+            ui.separator();
+            changed |= ui
+                .add(egui::DragValue::new(&mut self.pre).prefix("pre: "))
+                .changed();
+            changed |= ui
+                .add(egui::DragValue::new(&mut self.post).prefix("post: "))
+                .changed();
+            ui.label("samples");
+        });
+        if changed {
+            self.rearm();
+        }
+        self.poll(recorder?)
+    }
+
+    fn poll(&mut self, recorder: &Recorder) -> Option<Sweep> {
+        if self.recording != Some(recorder.started()) {
+            // a fresh recording; everything we've scanned belonged to the last one
+            self.recording = Some(recorder.started());
+            self.scanned = 0;
+            self.rearm();
+        }
+        let total = recorder.total();
+        if self.mode == Mode::Off || !self.armed {
+            self.scanned = total;
+            return None;
+        }
+        if self.watch.is_none() {
+            self.watch = Some(Watch::new(self.trigger()));
+        }
+        let watch = self.watch.as_mut().unwrap();
+        for (i, sample) in recorder.since(self.scanned).enumerate() {
+            // keep feeding it after it fires so it knows what the pins are up to when it's next needed
+            if watch.feed(sample) && self.fired.is_none() {
+                self.fired = Some(self.scanned.max(total - recorder.len() as u64) + i as u64);
+            }
+        }
+        self.scanned = total;
+
+        if let Some(fired) = self.fired
+            && total > fired + self.post as u64
+        {
+            self.fired = None;
+            self.last_sweep = Instant::now();
+            self.free_running = false;
+            if self.mode == Mode::Single {
+                self.armed = false;
+            }
+            let time = |n: u64| {
+                recorder
+                    .since(n)
+                    .next()
+                    .map_or(0.0, |sample| sample.host_time.as_secs_f64())
+            };
+            let at = time(fired);
+            let start = time(fired.saturating_sub(self.pre as u64)).min(at);
+            let end = time(fired + self.post as u64).max(at);
+            let margin = ((end - start) * MARGIN).max(0.001);
+            return Some(Sweep::Triggered {
+                start: start - margin,
+                end: end + margin,
+                at,
+            });
+        }
+        if self.mode == Mode::Auto
+            && !self.free_running
+            && self.last_sweep.elapsed() >= AUTO_TIMEOUT
+        {
+            self.free_running = true;
+            return Some(Sweep::FreeRun);
+        }
+        None
+    }
+}
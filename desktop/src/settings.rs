@@ -0,0 +1,38 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// the little bit the desktop app remembers between runs, kept as plain files in the user's config directory
+
+use std::path::PathBuf;
+
+fn dir() -> Option<PathBuf> {
+    // %APPDATA% on Windows, $XDG_CONFIG_HOME or ~/.config everywhere else
+    let base = std::env::var_os("APPDATA")
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("circuitdojo"))
+}
+
+pub fn last_port() -> Option<String> {
+    // the port the last successful connection was on
+    let port = std::fs::read_to_string(dir()?.join("last_port")).ok()?;
+    Some(port.trim().to_string())
+}
+
+pub fn remember_port(port: &str) {
+    // not being able to save this is no reason to bother anybody, so errors are dropped
+    if let Some(dir) = dir() {
+        let _ =
+            std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(dir.join("last_port"), port));
+    }
+}
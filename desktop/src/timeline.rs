@@ -0,0 +1,355 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// the waveform timeline: plots each pin's history out of a Recorder, logic-analyzer style
+use dojolib::{Recorder, board::PinStatus};
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Rect, Sense, Stroke, Vec2};
+use std::collections::BTreeMap;
+
+const ROW_HEIGHT: f32 = 28.0;
+const GUTTER: f32 = 110.0; // room on the left for pin names
+const SNAP_DISTANCE: f32 = 8.0; // how close (in pixels) a click has to be to an edge to land on it
+const MIN_SPAN: f64 = 0.001;
+const MAX_SPAN: f64 = 3600.0;
+
+struct Trace {
+    name: String,
+    scaled: bool, // analog/pwm traces get drawn as a level instead of a square wave
+    points: Vec<(f64, f32)>, // (seconds since recording start, level from 0 to 1)
+}
+
+// None means the pin isn't doing anything worth plotting
+fn level(status: PinStatus, adc_max: u16) -> Option<(f32, bool)> {
+    match status {
+        PinStatus::NoStatus => None,
+        PinStatus::DigitalOutputting(level)
+        | PinStatus::DigitalInputting(level)
+        | PinStatus::DigitalPullupInputting(level) => Some((if level { 1.0 } else { 0.0 }, false)),
+        PinStatus::AnalogOutputting(duty) => Some((duty as f32 / 255.0, true)),
+        PinStatus::AnalogInputting(value) => Some((value as f32 / adc_max as f32, true)),
+    }
+}
+
+fn traces(recorder: &Recorder, adc_max: u16) -> BTreeMap<u8, Trace> {
+    let mut traces = BTreeMap::new();
+    for sample in recorder.samples() {
+        let Some((level, scaled)) = level(sample.status, adc_max) else {
+            continue;
+        };
+        let trace = traces.entry(sample.pin).or_insert_with(|| Trace {
+            name: recorder
+                .pin(sample.pin)
+                .map(|pin| pin.name().to_string())
+                .unwrap_or_else(|| format!("Pin {}", sample.pin)),
+            scaled: false,
+            points: Vec::new(),
+        });
+        trace.scaled |= scaled;
+        // the recorder sees every status update, so drop the ones that don't change anything
+        if trace.points.last().is_none_or(|&(_, last)| last != level) {
+            trace.points.push((sample.host_time.as_secs_f64(), level));
+        }
+    }
+    traces
+}
+
+// picks a gridline spacing out of 1/2/5 * 10^n that gives roughly 8 lines across the view
+fn grid_step(span: f64) -> f64 {
+    let rough = span / 8.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= rough)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn format_time(seconds: f64) -> String {
+    let abs = seconds.abs();
+    if abs >= 1.0 {
+        format!("{:.3}s", seconds)
+    } else if abs >= 0.001 {
+        format!("{:.3}ms", seconds * 1e3)
+    } else {
+        format!("{:.1}us", seconds * 1e6)
+    }
+}
+
+pub struct Timeline {
+    span: f64,                 // how many seconds fit across the plot
+    end: Option<f64>,          // time at the right edge; None scrolls along with live data
+    cursors: [Option<f64>; 2], // left click places the first, right click the second
+    trigger: Option<f64>,      // where the last trigger fired, if the view is showing one
+    adc_max: u16,              // analog readings get scaled so this is the top of the row
+}
+
+impl Timeline {
+    pub fn new(adc_max: u16) -> Self {
+        Self {
+            span: 5.0,
+            end: None,
+            cursors: [None, None],
+            trigger: None,
+            adc_max,
+        }
+    }
+
+    // shows start to end (seconds since recording start) with a marker where a trigger fired
+    pub fn frame(&mut self, start: f64, end: f64, trigger: f64) {
+        self.span = (end - start).clamp(MIN_SPAN, MAX_SPAN);
+        self.end = Some(end);
+        self.trigger = Some(trigger);
+    }
+
+    pub fn follow(&mut self) {
+        self.end = None;
+        self.trigger = None;
+    }
+
+    // live should be true if the recorder is still taking samples, so the view keeps up with the wall clock
+    pub fn show(&mut self, ui: &mut egui::Ui, recorder: Option<&Recorder>, live: bool) {
+        let traces = recorder
+            .map(|recorder| traces(recorder, self.adc_max))
+            .unwrap_or_default();
+        let now = match recorder {
+            Some(recorder) if live => recorder.started().elapsed().as_secs_f64(),
+            Some(recorder) => recorder
+                .samples()
+                .map(|s| s.host_time.as_secs_f64())
+                .fold(0.0, f64::max),
+            None => 0.0,
+        };
+
+        ui.horizontal(|ui| {
+            if ui.button("Zoom in").clicked() {
+                self.zoom(0.5, None, now);
+            }
+            if ui.button("Zoom out").clicked() {
+                self.zoom(2.0, None, now);
+            }
+            if ui
+                .add_enabled(self.end.is_some(), egui::Button::new("Follow"))
+                .clicked()
+            {
+                self.follow();
+            }
+            if ui.button("Clear cursors").clicked() {
+                self.cursors = [None, None];
+            }
+            ui.label(format!("{} / div", format_time(grid_step(self.span))));
+            match self.cursors {
+                [Some(a), Some(b)] => {
+                    let delta = (b - a).abs();
+                    if delta > 0.0 {
+                        ui.label(format!(
+                            "Δt = {} ({:.2} Hz)",
+                            format_time(delta),
+                            1.0 / delta
+                        ));
+                    } else {
+                        ui.label("Δt = 0");
+                    }
+                }
+                [Some(_), None] | [None, Some(_)] => {
+                    ui.label("Right click to place the second cursor");
+                }
+                [None, None] => {
+                    ui.label("Drag to pan, scroll to zoom, click near an edge to place a cursor");
+                }
+            }
+        });
+
+        let rows = traces.len().max(1) as f32;
+        let size = Vec2::new(ui.available_width(), rows * ROW_HEIGHT + 16.0);
+        let (res, paint) = ui.allocate_painter(size, Sense::click_and_drag());
+        let plot = Rect::from_min_max(
+            res.rect.min + Vec2::new(GUTTER, 0.0),
+            res.rect.max - Vec2::new(0.0, 16.0),
+        );
+        paint.rect_filled(res.rect, 0.0, Color32::from_gray(20));
+
+        // panning and zooming stop the view from following the live data
+        let per_pixel = self.span / plot.width().max(1.0) as f64;
+        if res.dragged() {
+            let end = self.end.unwrap_or(now);
+            self.end = Some(end - res.drag_delta().x as f64 * per_pixel);
+        }
+        if let Some(hover) = res.hover_pos() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0.0 {
+                let factor = (-scroll as f64 * 0.005).exp();
+                self.zoom(factor, Some(self.time_at(hover.x, &plot, now)), now);
+            }
+        }
+
+        let end = self.end.unwrap_or(now);
+        let start = end - self.span;
+        let x_of = |t: f64| plot.left() + ((t - start) / self.span) as f32 * plot.width();
+
+        // time grid
+        let step = grid_step(self.span);
+        let mut tick = (start / step).ceil() * step;
+        while tick <= end {
+            let x = x_of(tick);
+            paint.line_segment(
+                [Pos2::new(x, plot.top()), Pos2::new(x, plot.bottom())],
+                Stroke::new(1.0, Color32::from_gray(50)),
+            );
+            paint.text(
+                Pos2::new(x, plot.bottom() + 2.0),
+                Align2::CENTER_TOP,
+                format_time(tick),
+                FontId::monospace(9.0),
+                Color32::GRAY,
+            );
+            tick += step;
+        }
+
+        if traces.is_empty() {
+            paint.text(
+                plot.center(),
+                Align2::CENTER_CENTER,
+                "No pin activity recorded",
+                FontId::proportional(12.0),
+                Color32::GRAY,
+            );
+        }
+
+        for (row, trace) in traces.values().enumerate() {
+            let top = plot.top() + row as f32 * ROW_HEIGHT;
+            let high = top + 4.0;
+            let low = top + ROW_HEIGHT - 4.0;
+            let y_of = |level: f32| low + (high - low) * level;
+            paint.text(
+                Pos2::new(res.rect.left() + 4.0, top + ROW_HEIGHT / 2.0),
+                Align2::LEFT_CENTER,
+                &trace.name,
+                FontId::monospace(10.0),
+                Color32::LIGHT_GRAY,
+            );
+
+            // only the points inside the view matter, plus the one before it so the trace starts at the left edge
+            let first = trace
+                .points
+                .partition_point(|&(t, _)| t < start)
+                .saturating_sub(1);
+            let last = trace.points.partition_point(|&(t, _)| t <= end);
+            let visible = &trace.points[first..last];
+            let mut line = Vec::with_capacity(visible.len() * 2 + 1);
+            for (i, &(t, level)) in visible.iter().enumerate() {
+                let x = x_of(t).max(plot.left());
+                if i > 0 {
+                    line.push(Pos2::new(
+                        x,
+                        line.last().map_or(y_of(level), |p: &Pos2| p.y),
+                    ));
+                }
+                line.push(Pos2::new(x, y_of(level)));
+            }
+            // hold the last level until the next change, or until now if there isn't one yet
+            let hold_until = if last < trace.points.len() {
+                end
+            } else {
+                now.min(end)
+            };
+            if let Some(&(t, level)) = visible.last()
+                && t <= hold_until
+            {
+                line.push(Pos2::new(x_of(hold_until), y_of(level)));
+            }
+            let color = if trace.scaled {
+                Color32::from_rgb(240, 200, 60)
+            } else {
+                Color32::from_rgb(80, 220, 80)
+            };
+            paint.add(egui::Shape::line(line, Stroke::new(1.5, color)));
+        }
+
+        // placing cursors snaps to the nearest edge on the clicked row, so delta-t measures edge to edge
+        let place = if res.clicked() {
+            Some(0)
+        } else if res.secondary_clicked() {
+            Some(1)
+        } else {
+            None
+        };
+        if let Some(which) = place
+            && let Some(pointer) = res.interact_pointer_pos()
+            && plot.contains(pointer)
+        {
+            let row = ((pointer.y - plot.top()) / ROW_HEIGHT) as usize;
+            let mut time = self.time_at(pointer.x, &plot, now);
+            if let Some(trace) = traces.values().nth(row) {
+                let nearest = trace
+                    .points
+                    .iter()
+                    .skip(1) // the first point is where recording started, not an edge
+                    .map(|&(t, _)| t)
+                    .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()));
+                if let Some(edge) = nearest
+                    && ((x_of(edge) - pointer.x).abs()) <= SNAP_DISTANCE
+                {
+                    time = edge;
+                }
+            }
+            self.cursors[which] = Some(time);
+        }
+        if let Some(t) = self.trigger
+            && t >= start
+            && t <= end
+        {
+            let x = x_of(t);
+            let color = Color32::from_rgb(255, 160, 40);
+            paint.line_segment(
+                [Pos2::new(x, plot.top()), Pos2::new(x, plot.bottom())],
+                Stroke::new(1.0, color),
+            );
+            paint.text(
+                Pos2::new(x + 2.0, plot.top()),
+                Align2::LEFT_TOP,
+                "T",
+                FontId::monospace(10.0),
+                color,
+            );
+        }
+        for (cursor, color) in self.cursors.iter().zip([
+            Color32::from_rgb(90, 160, 255),
+            Color32::from_rgb(255, 120, 200),
+        ]) {
+            if let Some(t) = *cursor
+                && t >= start
+                && t <= end
+            {
+                let x = x_of(t);
+                paint.line_segment(
+                    [Pos2::new(x, plot.top()), Pos2::new(x, plot.bottom())],
+                    Stroke::new(1.0, color),
+                );
+            }
+        }
+    }
+
+    fn time_at(&self, x: f32, plot: &Rect, now: f64) -> f64 {
+        let end = self.end.unwrap_or(now);
+        end - self.span + ((x - plot.left()) / plot.width().max(1.0)) as f64 * self.span
+    }
+
+    // scales the visible span, keeping the time under the pointer (or the right edge) in place
+    fn zoom(&mut self, factor: f64, anchor: Option<f64>, now: f64) {
+        let end = self.end.unwrap_or(now);
+        let span = (self.span * factor).clamp(MIN_SPAN, MAX_SPAN);
+        if let Some(anchor) = anchor {
+            self.end = Some(anchor + (end - anchor) * span / self.span);
+        }
+        self.span = span;
+    }
+}
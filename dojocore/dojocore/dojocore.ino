@@ -16,83 +16,843 @@ struct pindef {
   int physical_pin;
   bool is_analog;
   bool has_pullup;
+  bool has_pwm;
   const char* identifier;
 };
 
+#if defined(ARDUINO_AVR_MEGA2560)
+// the Mega has more pins than fit in the compact encoding, so pins from 64 up use the wide messages
+#define BOARD_NAME "Arduino Mega 2560 running CircuitDojo"
+#define CAPTURE_BYTES 4096 // of its 8K of RAM
+#define PATTERN_BYTES 1024
+
+pindef pins[] = {
+  { 2, false, true, true, "Digital 2" },
+  { 3, false, true, true, "Digital 3" },
+  { 4, false, true, true, "Digital 4" },
+  { 5, false, true, true, "Digital 5" },
+  { 6, false, true, true, "Digital 6" },
+  { 7, false, true, true, "Digital 7" },
+  { 8, false, true, true, "Digital 8" },
+  { 9, false, true, true, "Digital 9" },
+  { 10, false, true, true, "Digital 10" },
+  { 11, false, true, true, "Digital 11" },
+  { 12, false, true, true, "Digital 12" },
+  { 13, false, true, true, "Digital 13" },
+  { 14, false, true, false, "Digital 14" },
+  { 15, false, true, false, "Digital 15" },
+  { 16, false, true, false, "Digital 16" },
+  { 17, false, true, false, "Digital 17" },
+  { 18, false, true, false, "Digital 18" },
+  { 19, false, true, false, "Digital 19" },
+  { 20, false, true, false, "Digital 20" },
+  { 21, false, true, false, "Digital 21" },
+  { 22, false, true, false, "Digital 22" },
+  { 23, false, true, false, "Digital 23" },
+  { 24, false, true, false, "Digital 24" },
+  { 25, false, true, false, "Digital 25" },
+  { 26, false, true, false, "Digital 26" },
+  { 27, false, true, false, "Digital 27" },
+  { 28, false, true, false, "Digital 28" },
+  { 29, false, true, false, "Digital 29" },
+  { 30, false, true, false, "Digital 30" },
+  { 31, false, true, false, "Digital 31" },
+  { 32, false, true, false, "Digital 32" },
+  { 33, false, true, false, "Digital 33" },
+  { 34, false, true, false, "Digital 34" },
+  { 35, false, true, false, "Digital 35" },
+  { 36, false, true, false, "Digital 36" },
+  { 37, false, true, false, "Digital 37" },
+  { 38, false, true, false, "Digital 38" },
+  { 39, false, true, false, "Digital 39" },
+  { 40, false, true, false, "Digital 40" },
+  { 41, false, true, false, "Digital 41" },
+  { 42, false, true, false, "Digital 42" },
+  { 43, false, true, false, "Digital 43" },
+  { 44, false, true, true, "Digital 44" },
+  { 45, false, true, true, "Digital 45" },
+  { 46, false, true, true, "Digital 46" },
+  { 47, false, true, false, "Digital 47" },
+  { 48, false, true, false, "Digital 48" },
+  { 49, false, true, false, "Digital 49" },
+  { 50, false, true, false, "Digital 50" },
+  { 51, false, true, false, "Digital 51" },
+  { 52, false, true, false, "Digital 52" },
+  { 53, false, true, false, "Digital 53" },
+  { A0, true, false, false, "Analog 0" },
+  { A1, true, false, false, "Analog 1" },
+  { A2, true, false, false, "Analog 2" },
+  { A3, true, false, false, "Analog 3" },
+  { A4, true, false, false, "Analog 4" },
+  { A5, true, false, false, "Analog 5" },
+  { A6, true, false, false, "Analog 6" },
+  { A7, true, false, false, "Analog 7" },
+  { A8, true, false, false, "Analog 8" },
+  { A9, true, false, false, "Analog 9" },
+  { A10, true, false, false, "Analog 10" },
+  { A11, true, false, false, "Analog 11" },
+  { A12, true, false, false, "Analog 12" },
+  { A13, true, false, false, "Analog 13" },
+  { A14, true, false, false, "Analog 14" },
+  { A15, true, false, false, "Analog 15" },
+};
+#elif defined(ARDUINO_AVR_PROMICRO)
+// no 11-13, but 14-16 and the serial pins, since it talks to the host over its own USB
+#define BOARD_NAME "SparkFun Pro Micro running CircuitDojo"
+#define CAPTURE_BYTES 1024 // of its 2.5K of RAM
+#define PATTERN_BYTES 256
+
+pindef pins[] = {
+  { 0, false, true, false, "Digital 0" },
+  { 1, false, true, false, "Digital 1" },
+  { 2, false, true, false, "Digital 2" },
+  { 3, false, true, true, "Digital 3" },
+  { 4, false, true, false, "Digital 4" },
+  { 5, false, true, true, "Digital 5" },
+  { 6, false, true, true, "Digital 6" },
+  { 7, false, true, false, "Digital 7" },
+  { 8, false, true, false, "Digital 8" },
+  { 9, false, true, true, "Digital 9" },
+  { 10, false, true, true, "Digital 10" },
+  { 14, false, true, false, "Digital 14" },
+  { 15, false, true, false, "Digital 15" },
+  { 16, false, true, false, "Digital 16" },
+  { A0, true, false, false, "Analog 0" },
+  { A1, true, false, false, "Analog 1" },
+  { A2, true, false, false, "Analog 2" },
+  { A3, true, false, false, "Analog 3" },
+};
+#elif defined(ARDUINO_RASPBERRY_PI_PICO)
+// every GPIO does PWM and pullups. analogRead stays at its default 10 bits, so readings scale like an UNO's (at 3.3V)
+#define BOARD_NAME "Raspberry Pi Pico running CircuitDojo"
+#define CAPTURE_BYTES 16384 // it's got 264K, but the capture sizes are 16 bits
+#define PATTERN_BYTES 4096
+
 pindef pins[] = {
-  { 3, false, true, "Digital 3" },
-  { 4, false, true, "Digital 4" },
-  { 5, false, true, "Digital 5" },
-  { 6, false, true, "Digital 6" },
-  { 7, false, true, "Digital 7" },
-  { 8, false, true, "Digital 8" },
-  { 9, false, true, "Digital 9" },
-  { 10, false, true, "Digital 10" },
-  { 11, false, true, "Digital 11" },
-  { 12, false, true, "Digital 12" },
-  { 13, false, true, "Digital 13" },
+  { 0, false, true, true, "GP0" },
+  { 1, false, true, true, "GP1" },
+  { 2, false, true, true, "GP2" },
+  { 3, false, true, true, "GP3" },
+  { 4, false, true, true, "GP4" },
+  { 5, false, true, true, "GP5" },
+  { 6, false, true, true, "GP6" },
+  { 7, false, true, true, "GP7" },
+  { 8, false, true, true, "GP8" },
+  { 9, false, true, true, "GP9" },
+  { 10, false, true, true, "GP10" },
+  { 11, false, true, true, "GP11" },
+  { 12, false, true, true, "GP12" },
+  { 13, false, true, true, "GP13" },
+  { 14, false, true, true, "GP14" },
+  { 15, false, true, true, "GP15" },
+  { 16, false, true, true, "GP16" },
+  { 17, false, true, true, "GP17" },
+  { 18, false, true, true, "GP18" },
+  { 19, false, true, true, "GP19" },
+  { 20, false, true, true, "GP20" },
+  { 21, false, true, true, "GP21" },
+  { 22, false, true, true, "GP22" },
+  { A0, true, false, false, "Analog 0" },
+  { A1, true, false, false, "Analog 1" },
+  { A2, true, false, false, "Analog 2" },
 };
+#else
+// the Nano and the Leonardo have the same pins dojocore uses on the UNO, just a different name for the host to look up
+#if defined(ARDUINO_AVR_NANO)
+#define BOARD_NAME "Arduino Nano running CircuitDojo"
+#elif defined(ARDUINO_AVR_LEONARDO)
+#define BOARD_NAME "Arduino Leonardo running CircuitDojo"
+#else
+#define BOARD_NAME "Arduino UNO R3 running CircuitDojo"
+#endif
+#define CAPTURE_BYTES 1024 // of the 2K of RAM they've got
+#define PATTERN_BYTES 256
+
+pindef pins[] = {
+  { 3, false, true, true, "Digital 3" },
+  { 4, false, true, false, "Digital 4" },
+  { 5, false, true, true, "Digital 5" },
+  { 6, false, true, true, "Digital 6" },
+  { 7, false, true, false, "Digital 7" },
+  { 8, false, true, false, "Digital 8" },
+  { 9, false, true, true, "Digital 9" },
+  { 10, false, true, true, "Digital 10" },
+  { 11, false, true, true, "Digital 11" },
+  { 12, false, true, false, "Digital 12" },
+  { 13, false, true, false, "Digital 13" },
+  { A0, true, false, false, "Analog 0" },
+  { A1, true, false, false, "Analog 1" },
+  { A2, true, false, false, "Analog 2" },
+  { A3, true, false, false, "Analog 3" },
+  { A4, true, false, false, "Analog 4" },
+  { A5, true, false, false, "Analog 5" },
+};
+#endif
 
 #define DIG_NONE 1024
 #define DIG_LOW 1025
 #define DIG_HIGH 1026
 
-uint16_t states[] = { // 0-1023 = analog, 1024=none, 1025=digital low, 1026=digital high
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-  DIG_NONE,
-};
+const int pinCount = sizeof(pins) / sizeof(pindef);
 
-char modes[] = {
-  0,
-  0,
-  0,
-  0,
-  0,
-  0,
-  0,
-  0,
-  0,
-  0,
-  0,
-}; // 0=none, 1=input, 2=output
+uint16_t states[pinCount]; // 0-1023 = analog, 1024=none, 1025=digital low, 1026=digital high
 
-const int pinCount = sizeof(pins) / sizeof(pindef);
+char modes[pinCount]; // 0=none, 1=input, 2=output
+
+// protocol v2 wraps everything in a checksummed frame: START, LEN, SEQ, TYPE, PAYLOAD (LEN bytes), CRC.
+// the CRC is CRC-8 (polynomial 0x07) over LEN through PAYLOAD. see dojolib's opcodes.rs for the rest
+#define FRAME_START 0xA5
+#define MAX_PAYLOAD 64
+#define COMPACT_PINS 64 // pins below this fit in the packed single-byte messages
+
+uint8_t version = 1; // back to 1 at every handshake
+bool timestamps = false; // pin states carry micros() (v2 only); also off at every handshake
+
+uint8_t frame[MAX_PAYLOAD + 5];
+int framed = 0; // how much of frame[] is filled in
+
+#define MAX_CAPTURE_PINS 16
+#define CAPTURE_CHUNK 48 // packed sample bytes per CAPTURE_DATA
+uint8_t captureBuf[CAPTURE_BYTES];
+
+// pattern playback: the host uploads a table of levels, then we step through it from loop() (see dojolib's pattern.rs)
+#define MAX_PLAY_PINS 16
+uint8_t patternBuf[PATTERN_BYTES];
+int patternLoaded = 0; // how much of the table has come in, in order from the start
+bool playing = false;
+uint8_t playSeq; // of the PLAY_PATTERN, for its PATTERN_DONE
+uint8_t playPins[MAX_PLAY_PINS];
+int playPinCount;
+uint16_t playSteps;
+uint16_t playRepeat; // 0 is forever
+uint16_t playRound;
+uint16_t playStep; // the next one to play
+long playShown = -1; // the last one played
+unsigned long playPeriod;
+unsigned long playNext;
 
 void setup() {
   Serial.begin(115200);
+  for (int i = 0; i < pinCount; i ++) {
+    states[i] = DIG_NONE;
+    modes[i] = 0;
+  }
+}
+
+uint8_t crc8(uint8_t crc, uint8_t byte) {
+  crc ^= byte;
+  for (int i = 0; i < 8; i ++) {
+    crc = (crc & 0x80) ? (crc << 1) ^ 0x07 : crc << 1;
+  }
+  return crc;
+}
+
+void send(uint8_t seq, uint8_t type, const uint8_t* payload, int len) {
+  // one message to the host, framed or not depending on what we negotiated
+  if (version >= 2) {
+    uint8_t header[] = { FRAME_START, (uint8_t)len, seq, type };
+    Serial.write(header, 4);
+    Serial.write(payload, len);
+    uint8_t crc = 0;
+    for (int i = 1; i < 4; i ++) {
+      crc = crc8(crc, header[i]);
+    }
+    for (int i = 0; i < len; i ++) {
+      crc = crc8(crc, payload[i]);
+    }
+    Serial.write(crc);
+  }
+  else if (type == 0x85 && payload[0] < COMPACT_PINS) { // digital states get packed into a single byte
+    Serial.write(payload[0] | (payload[1] ? 0x40 : 0));
+  }
+  else {
+    Serial.write(type);
+    Serial.write(payload, len);
+    if (type == 0x81 || type == 0x82 || type == 0x87) { // strings are null terminated
+      Serial.write(0);
+    }
+  }
+}
+
+void reply(uint8_t seq, uint8_t type) {
+  send(seq, type, NULL, 0);
+}
+
+void writeAnalogState(int pindex, int val, unsigned long at) {
+  uint8_t payload[] = { (uint8_t)pindex, (uint8_t)(val & 0xFF), (uint8_t)(val >> 8), 0, 0, 0, 0 };
+  if (timestamps) {
+    memcpy(payload + 3, &at, 4); // AVR is little endian already
+    send(0, 0x89, payload, 7);
+  }
+  else {
+    send(0, 0x83, payload, 3);
+  }
+}
+
+void writeDigitalState(int pindex, int val, unsigned long at) {
+  uint8_t payload[] = { (uint8_t)pindex, (uint8_t)(val ? 1 : 0), 0, 0, 0, 0 };
+  if (timestamps) {
+    memcpy(payload + 2, &at, 4);
+    send(0, 0x88, payload, 6);
+  }
+  else {
+    send(0, 0x85, payload, 2);
+  }
+}
+
+void stopPattern() {
+  // tell the host where it left the pins
+  if (playing) {
+    playing = false;
+    uint16_t step = playShown < 0 ? 0xFFFF : playShown;
+    uint8_t payload[] = { (uint8_t)(step & 0xFF), (uint8_t)(step >> 8) };
+    send(playSeq, 0x8C, payload, 2);
+  }
+}
+
+void playPattern() {
+  // put out the pattern's next step if it's time. falling behind just plays the late ones back to back.
+  // this gets called from anywhere that might keep us busy for a while, so it has to be quick when there's nothing to do
+  if (!playing || (long)(micros() - playNext) < 0) {
+    return;
+  }
+  long bit = (long)playStep * playPinCount;
+  for (int i = 0; i < playPinCount; i ++, bit ++) {
+    if (modes[playPins[i]] == 2) { // a pin switched away from being an output is left alone
+      digitalWrite(pins[playPins[i]].physical_pin, (patternBuf[bit / 8] & (1 << (bit % 8))) ? HIGH : LOW);
+    }
+  }
+  playNext += playPeriod;
+  playShown = playStep;
+  playStep ++;
+  if (playStep == playSteps) {
+    playStep = 0;
+    playRound ++;
+    if (playRepeat != 0 && playRound == playRepeat) {
+      stopPattern();
+    }
+  }
+}
+
+void startPattern(uint8_t seq, const uint8_t* args, int len) {
+  // [period (u32), repeat (u16), steps (u16), pin count, pins...]
+  stopPattern();
+  unsigned long period;
+  memcpy(&period, args, 4);
+  uint16_t steps = args[6] | (args[7] << 8);
+  int np = args[8];
+  bool valid = np >= 1 && np <= MAX_PLAY_PINS && len == 9 + np && steps > 0 && steps < 0xFFFF && ((long)steps * np + 7) / 8 <= patternLoaded;
+  for (int i = 0; valid && i < np; i ++) {
+    valid = args[9 + i] < pinCount && !pins[args[9 + i]].is_analog;
+  }
+  if (!valid) {
+    reply(seq, 0xFE);
+    return;
+  }
+  memcpy(playPins, args + 9, np);
+  playPinCount = np;
+  playSteps = steps;
+  playRepeat = args[4] | (args[5] << 8);
+  playRound = 0;
+  playStep = 0;
+  playShown = -1;
+  playPeriod = period;
+  playSeq = seq;
+  playNext = micros();
+  playing = true;
+  reply(seq, 0xFF);
+  playPattern();
+}
+
+#define MAX_BUS_PINS 16
+
+void writePins(uint8_t seq, const uint8_t* args, int len) {
+  // [count, pins..., levels (packed, least significant bit first)]: set them all at the same moment, so whatever's
+  // on the other end of a bus never sees a mix of the old value and the new one
+  int n = args[0];
+  const uint8_t* busPins = args + 1;
+  const uint8_t* levels = args + 1 + n;
+  bool valid = n >= 1 && n <= MAX_BUS_PINS && len == 1 + n + (n + 7) / 8;
+  for (int i = 0; valid && i < n; i ++) {
+    valid = busPins[i] < pinCount && !pins[busPins[i]].is_analog && modes[busPins[i]] == 2;
+  }
+  if (!valid) {
+    reply(seq, 0xFE);
+    return;
+  }
+#ifdef __AVR__
+  // work out what each port register needs, then write them all with interrupts off. pins on the same port
+  // change together, and different ports are a couple of cycles apart
+  volatile uint8_t* ports[MAX_BUS_PINS];
+  uint8_t set[MAX_BUS_PINS];
+  uint8_t clear[MAX_BUS_PINS];
+  int portCount = 0;
+  for (int i = 0; i < n; i ++) {
+    int physical = pins[busPins[i]].physical_pin;
+    digitalRead(physical); // turns off PWM on the pin, like digitalWrite would, without changing anything else
+    volatile uint8_t* port = portOutputRegister(digitalPinToPort(physical));
+    int p = 0;
+    while (p < portCount && ports[p] != port) {
+      p ++;
+    }
+    if (p == portCount) {
+      ports[p] = port;
+      set[p] = 0;
+      clear[p] = 0;
+      portCount ++;
+    }
+    if (levels[i / 8] & (1 << (i % 8))) {
+      set[p] |= digitalPinToBitMask(physical);
+    }
+    else {
+      clear[p] |= digitalPinToBitMask(physical);
+    }
+  }
+  noInterrupts();
+  for (int p = 0; p < portCount; p ++) {
+    *ports[p] = (*ports[p] & ~clear[p]) | set[p];
+  }
+  interrupts();
+#else
+  for (int i = 0; i < n; i ++) {
+    digitalWrite(pins[busPins[i]].physical_pin, (levels[i / 8] & (1 << (i % 8))) ? HIGH : LOW);
+  }
+#endif
+  reply(seq, 0xFF);
 }
 
 uint16_t subsc_wavelength = 0;
 long last_update = 0;
 
-void doPinUpdates() {
+void doPinUpdates(bool analog) {
+  // send every input that changed since we last said. with analog false, only the digital ones
   for (int i = 0; i < pinCount; i ++) {
+    playPattern(); // analogRead is slow enough to throw a fast pattern off
     if (modes[i] != 1) {
       continue;
     }
     if (pins[i].is_analog) {
-  
+      if (!analog) {
+        continue;
+      }
+      unsigned long at = micros();
+      int val = analogRead(pins[i].physical_pin);
+      if (states[i] != val) {
+        states[i] = val;
+        writeAnalogState(i, val, at);
+      }
     }
     else {
+      unsigned long at = micros();
       int val = digitalRead(pins[i].physical_pin);
       int state = val ? DIG_HIGH : DIG_LOW;
       if (states[i] != state) {
         states[i] = state;
-        Serial.write(i | (val ? 0x40 : 0));
+        writeDigitalState(i, val, at);
       }
     }
   }
 }
 
+void setMode(uint8_t seq, int pindex, int mode, int arduinoMode) {
+  if (pindex >= pinCount || (arduinoMode == INPUT_PULLUP && !pins[pindex].has_pullup)) {
+    reply(seq, 0xFE);
+  }
+  else {
+    reply(seq, 0xFF);
+    pinMode(pins[pindex].physical_pin, arduinoMode);
+    modes[pindex] = mode;
+  }
+}
+
+void digitalWritePin(int pindex, bool high) {
+  if (pindex < pinCount) { // analog pins included: A0-A5 on an UNO are digital pins too
+    digitalWrite(pins[pindex].physical_pin, high ? HIGH : LOW);
+  }
+}
+
+bool edgeMatches(int edge, int before, int after) {
+  // 0 rising, 1 falling, 2 either
+  if (edge == 0) {
+    return !before && after;
+  }
+  if (edge == 1) {
+    return before && !after;
+  }
+  return before != after;
+}
+
+bool captureBit(long bit) {
+  return captureBuf[bit / 8] & (1 << (bit % 8));
+}
+
+void capture(uint8_t seq, const uint8_t* args, int len) {
+  // [period (u32), count (u16), pin count, pins..., trigger]. see dojolib's capture.rs and trigger.rs.
+  // sample the pins round and round captureBuf until the trigger fires, fill in the rest, then send it all.
+  // nothing else happens until it's done; the serial buffer holds onto anything the host sends meanwhile
+  unsigned long period;
+  memcpy(&period, args, 4);
+  uint16_t count = args[4] | (args[5] << 8);
+  int np = args[6];
+  const uint8_t* capturePins = args + 7;
+  const uint8_t* trigger = capturePins + np;
+  int triggerLen = len - 7 - np;
+  long bits = (long)np * count;
+  bool valid = np >= 1 && np <= MAX_CAPTURE_PINS && triggerLen >= 5 && count > 0 && bits <= CAPTURE_BYTES * 8L;
+  for (int i = 0; valid && i < np; i ++) {
+    valid = capturePins[i] < pinCount && !pins[capturePins[i]].is_analog;
+  }
+  int kind = valid ? trigger[0] : -1;
+  // widen before shifting: an int is only 16 bits on AVR, and a timeout past 32767ms would come out negative
+  unsigned long timeout = valid ? ((unsigned long)trigger[1] | ((unsigned long)trigger[2] << 8)) * 1000UL : 0;
+  uint16_t pre = valid ? (uint16_t)((uint16_t)trigger[3] | ((uint16_t)trigger[4] << 8)) : 0;
+  const uint8_t* targs = trigger + 5;
+  int targsLen = triggerLen - 5;
+  valid = valid && pre < count;
+  if (kind == 0) {
+    valid = valid && targsLen == 0;
+  }
+  else if (kind == 1) { // edge: pin, edge
+    valid = valid && targsLen == 2 && targs[0] < pinCount && !pins[targs[0]].is_analog && targs[1] <= 2;
+  }
+  else if (kind == 2) { // pattern: n, then pin, level n times
+    valid = valid && targsLen >= 1 && targs[0] >= 1 && targs[0] <= 8 && targsLen == 1 + targs[0] * 2;
+    for (int i = 0; valid && i < targs[0]; i ++) {
+      valid = targs[1 + i * 2] < pinCount && !pins[targs[1 + i * 2]].is_analog;
+    }
+  }
+  else if (kind == 3) { // pulse longer than: pin, level, min (u32)
+    valid = valid && targsLen == 6 && targs[0] < pinCount && !pins[targs[0]].is_analog;
+  }
+  else if (kind == 4) { // analog threshold: pin, value (u16), edge
+    valid = valid && targsLen == 4 && targs[0] < pinCount && pins[targs[0]].is_analog && targs[3] <= 2;
+  }
+  else {
+    valid = false;
+  }
+  if (!valid) {
+    reply(seq, 0xFE);
+    return;
+  }
+  unsigned long minPulse = 0;
+  if (kind == 3) {
+    memcpy(&minPulse, targs + 2, 4);
+  }
+  uint16_t threshold = kind == 4 ? targs[1] | (targs[2] << 8) : 0;
+  int prev = kind == 2 ? 0 : -1; // the trigger's last reading; -1 before the first. a pattern that's matched all along fires as soon as it's allowed to
+  unsigned long pulseStart = 0;
+  bool inPulse = false;
+  long firedAt = -1;
+  bool triggered = false;
+  unsigned long first = micros();
+  unsigned long next = first;
+  unsigned long last = first;
+  long sample = 0;
+  while (true) {
+    while ((long)(micros() - next) < 0) { // wait for this sample's turn
+      playPattern(); // so a capture can watch what a pattern does
+    }
+    next += period;
+    last = micros();
+    if (sample == 0) {
+      first = last;
+    }
+    long bit = (sample % count) * np;
+    for (int i = 0; i < np; i ++, bit ++) {
+      if (digitalRead(pins[capturePins[i]].physical_pin)) {
+        captureBuf[bit / 8] |= 1 << (bit % 8);
+      }
+      else {
+        captureBuf[bit / 8] &= ~(1 << (bit % 8));
+      }
+    }
+    bool hit = false;
+    if (kind == 0) {
+      hit = true;
+    }
+    else if (kind == 1) {
+      int now = digitalRead(pins[targs[0]].physical_pin) ? 1 : 0;
+      hit = prev >= 0 && edgeMatches(targs[1], prev, now);
+      prev = now;
+    }
+    else if (kind == 2) {
+      int now = 1;
+      for (int i = 0; i < targs[0]; i ++) {
+        if ((digitalRead(pins[targs[1 + i * 2]].physical_pin) ? 1 : 0) != (targs[2 + i * 2] ? 1 : 0)) {
+          now = 0;
+        }
+      }
+      hit = now && prev == 0;
+      if (sample >= pre) {
+        prev = now;
+      }
+    }
+    else if (kind == 3) {
+      int now = digitalRead(pins[targs[0]].physical_pin) ? 1 : 0;
+      int level = targs[1] ? 1 : 0;
+      if (now == level && prev == !level) {
+        pulseStart = last;
+        inPulse = true;
+      }
+      else if (now != level && prev == level) {
+        hit = inPulse && last - pulseStart > minPulse;
+        inPulse = false;
+      }
+      prev = now;
+    }
+    else {
+      int now = analogRead(pins[targs[0]].physical_pin) >= threshold ? 1 : 0;
+      hit = prev >= 0 && edgeMatches(targs[3], prev, now);
+      prev = now;
+    }
+    // it can't fire until there are enough samples to come before it
+    if (firedAt < 0 && sample >= pre) {
+      if (hit) {
+        firedAt = sample;
+        triggered = true;
+      }
+      else if (last - first >= timeout) {
+        firedAt = sample;
+      }
+    }
+    if (firedAt >= 0 && sample - firedAt >= count - pre - 1) {
+      break;
+    }
+    sample ++;
+  }
+  unsigned long taken = sample + 1;
+  long oldest = taken % count;
+  // send it oldest sample first, which means starting partway round the buffer
+  int packedLen = (bits + 7) / 8;
+  uint8_t payload[CAPTURE_CHUNK + 2];
+  for (int offset = 0; offset < packedLen; offset += CAPTURE_CHUNK) {
+    int n = min(CAPTURE_CHUNK, packedLen - offset);
+    payload[0] = offset & 0xFF;
+    payload[1] = offset >> 8;
+    for (int i = 0; i < n; i ++) {
+      uint8_t byte = 0;
+      for (int j = 0; j < 8; j ++) {
+        long out = (long)(offset + i) * 8 + j;
+        if (out < bits && captureBit(((oldest + out / np) % count) * np + out % np)) {
+          byte |= 1 << j;
+        }
+      }
+      payload[2 + i] = byte;
+    }
+    send(seq, 0x8A, payload, n + 2);
+  }
+  uint8_t done[9];
+  unsigned long elapsed = last - first;
+  memcpy(done, &elapsed, 4);
+  memcpy(done + 4, &taken, 4);
+  done[8] = triggered;
+  send(seq, 0x8B, done, 9);
+  reply(seq, 0xFF);
+}
+
+int argCount(int opcode) {
+  // how many bytes follow each v1 opcode
+  if (opcode == 0x81 || opcode == 0x82 || opcode == 0x83 || opcode == 0x87 || opcode == 0x89) {
+    return 1;
+  }
+  if (opcode == 0x84 || opcode == 0x85 || opcode == 0x88) {
+    return 2;
+  }
+  return 0;
+}
+
+bool command(uint8_t seq, int type, const uint8_t* args, int len) {
+  // run one command from the host. false means drop back to waiting for a handshake
+  if (type == 0x80 && len <= 1) {
+    reply(seq, 0xFF);
+    if (len == 1) {
+      // a v2 host told us its version, so tell it ours: protocol, oldest host we serve,
+      // flags (analog in, pwm, pullups, timestamps, bus writes), pin count, capture buffer size, pattern table size.
+      // timestamps and bus writes work everywhere; the rest depends on what's in the pin table
+      uint8_t flags = 0x18;
+      for (int i = 0; i < pinCount; i ++) {
+        flags |= (pins[i].is_analog ? 0x01 : 0x00) | (pins[i].has_pwm ? 0x02 : 0x00) | (pins[i].has_pullup ? 0x04 : 0x00);
+      }
+      uint8_t capabilities[] = { version, 1, flags, 0x00, pinCount & 0xFF, pinCount >> 8, CAPTURE_BYTES & 0xFF, CAPTURE_BYTES >> 8, PATTERN_BYTES & 0xFF, PATTERN_BYTES >> 8 };
+      send(seq, 0x86, capabilities, 10);
+    }
+    uint8_t bounds[] = { 0x10, 0x00 };
+    send(seq, 0x80, bounds, 2);
+    uint8_t description[MAX_PAYLOAD];
+    for (int i = 0; i < pinCount; i ++) {
+      uint8_t flags = (pins[i].is_analog ? 0x80 : 0x00) | (pins[i].has_pullup ? 0x40 : 0x00);
+      int n = strlen(pins[i].identifier);
+      if (i < COMPACT_PINS) {
+        description[0] = i | flags;
+        memcpy(description + 1, pins[i].identifier, n);
+        send(seq, 0x81, description, n + 1);
+      }
+      else { // too big for the descriptor byte, so the pin gets a byte to itself
+        description[0] = i;
+        description[1] = flags;
+        memcpy(description + 2, pins[i].identifier, n);
+        send(seq, 0x87, description, n + 2);
+      }
+      if (pins[i].has_pwm) {
+        uint8_t pindex = i;
+        send(seq, 0x84, &pindex, 1);
+      }
+    }
+    const char* name = BOARD_NAME;
+    send(seq, 0x82, (const uint8_t*)name, strlen(name));
+  }
+  else if (type == 0x81 && len == 1) {
+    setMode(seq, args[0], 1, INPUT);
+  }
+  else if (type == 0x82 && len == 1) {
+    setMode(seq, args[0], 2, OUTPUT);
+  }
+  else if (type == 0x83 && len == 1) { // input with pullup
+    setMode(seq, args[0], 1, INPUT_PULLUP);
+  }
+  else if (type == 0x88 && len == 2) { // digital write for any pin, no answer
+    digitalWritePin(args[0], args[1]);
+  }
+  else if (type == 0x85 && len == 2) { // pwm write
+    int pindex = args[0];
+    if (pindex >= pinCount || !pins[pindex].has_pwm) {
+      reply(seq, 0xFE);
+    }
+    else {
+      reply(seq, 0xFF);
+      analogWrite(pins[pindex].physical_pin, args[1]);
+    }
+  }
+  else if (type == 0x84 && len == 2) { // subscribe to updates
+    subsc_wavelength = args[0] | (args[1] << 8);
+    reply(seq, 0xFF);
+  }
+  else if (type == 0x86 && len == 0) { // write all pin values, then ACK
+    for (int i = 0; i < pinCount; i ++) {
+      if (modes[i] == 1) {
+        if (pins[i].is_analog) {
+          writeAnalogState(i, analogRead(pins[i].physical_pin), micros());
+        }
+        else {
+          writeDigitalState(i, digitalRead(pins[i].physical_pin), micros());
+        }
+      }
+    }
+    reply(seq, 0xFF);
+  }
+  else if (type == 0x89 && len == 1) { // timestamps on or off
+    if (version >= 2) {
+      reply(seq, 0xFF);
+      timestamps = args[0];
+    }
+    else {
+      reply(seq, 0xFE); // v1 has nowhere to put them
+    }
+  }
+  else if (type == 0x8A && len >= 7 && version >= 2) { // burst capture
+    capture(seq, args, len);
+  }
+  else if (type == 0x8B && len >= 2 && version >= 2) { // a chunk of pattern table
+    stopPattern();
+    int offset = args[0] | (args[1] << 8);
+    int n = len - 2;
+    if (offset == 0) {
+      patternLoaded = 0;
+    }
+    if (offset == patternLoaded && offset + n <= PATTERN_BYTES) {
+      memcpy(patternBuf + offset, args + 2, n);
+      patternLoaded += n;
+      reply(seq, 0xFF);
+    }
+    else {
+      patternLoaded = 0; // a chunk went missing, so nothing can play until the whole table comes again
+      reply(seq, 0xFE);
+    }
+  }
+  else if (type == 0x8C && len >= 9 && version >= 2) { // play the pattern table
+    startPattern(seq, args, len);
+  }
+  else if (type == 0x8D && len == 0 && version >= 2) { // stop playing it
+    stopPattern();
+    reply(seq, 0xFF);
+  }
+  else if (type == 0x8E && len >= 1 && version >= 2) { // several digital writes at once
+    writePins(seq, args, len);
+  }
+  else if (type == 0x87 && len == 1) { // negotiate a protocol version
+    if (version == 1 && args[0] == 2) {
+      reply(seq, 0xFF); // still unframed, so the host can tell
+      version = 2;
+      framed = 0;
+    }
+    else if (version >= 2 && args[0] == 1) {
+      return false; // the host is starting over
+    }
+    else {
+      reply(seq, 0xFE);
+    }
+  }
+  else if (type == 0xFF && len == 0 && version >= 2) {
+    reply(seq, 0xFF);
+  }
+  else {
+    reply(seq, 0xFE); // send an error code and get out of here skoob
+    // v1 has no way of finding where the next command starts, so it gives up on the session; v2 just moves on
+    return version >= 2;
+  }
+  return true;
+}
+
+void resync() {
+  // throw away the START we were looking at and skip ahead to the next one
+  int i = 1;
+  while (i < framed && frame[i] != FRAME_START) {
+    i ++;
+  }
+  memmove(frame, frame + i, framed - i);
+  framed -= i;
+}
+
+bool feedFrame(uint8_t byte) {
+  // v2: collect bytes until there's a whole frame with a good CRC, then run it
+  frame[framed ++] = byte;
+  while (framed > 0) {
+    if (frame[0] != FRAME_START) {
+      resync();
+      continue;
+    }
+    if (framed < 2) {
+      return true;
+    }
+    if (frame[1] > MAX_PAYLOAD) {
+      resync();
+      continue;
+    }
+    int total = frame[1] + 5;
+    if (framed < total) {
+      return true;
+    }
+    uint8_t crc = 0;
+    for (int i = 1; i < total - 1; i ++) {
+      crc = crc8(crc, frame[i]);
+    }
+    if (crc != frame[total - 1]) {
+      resync();
+      continue;
+    }
+    if (!command(frame[2], frame[3], frame + 4, frame[1])) {
+      framed = 0;
+      return false;
+    }
+    if (version < 2) {
+      return true;
+    }
+    memmove(frame, frame + total, framed - total);
+    framed -= total;
+  }
+  return true;
+}
+
 void loop() {
   while (Serial.available() == 0) {} // block until byte
   int byte = Serial.read();
@@ -102,86 +862,45 @@ void loop() {
   else {
     return;
   }
+  version = 1;
+  timestamps = false;
+  playing = false;
 
   while (true) {
     // past this point the handshake is complete! let's do some normal operation tasks:
     while (Serial.available() == 0) {
+      playPattern();
       if (subsc_wavelength != 0) {
         if (millis() - last_update > subsc_wavelength) {
           last_update = millis();
-          doPinUpdates();
+          doPinUpdates(true);
+        }
+        else if (timestamps) {
+          // watch the digital inputs all the time, so edges get stamped when they happen rather than at the next sample
+          doPinUpdates(false);
         }
       }
     }
+    playPattern(); // and between commands, when the host keeps us busy
     byte = Serial.read();
-    if ((~byte) & 0x80) { // if the high bit is unset
-      int pindex = byte & 0b00111111;
-      if (pins[pindex].is_analog) {
-        // TODO
-      }
-      else {
-        digitalWrite(pins[pindex].physical_pin, (byte & 0x40) ? HIGH : LOW);
+    if (version >= 2) {
+      if (!feedFrame(byte)) {
+        return;
       }
     }
-    else if (byte == 0x80) {
-      uint8_t capabilities[] = { 0xFF, 0x80, 0x10, 0x00 };
-      Serial.write(capabilities, 4);
-      for (int i = 0; i < pinCount; i ++) {
-        Serial.write(0x81);
-        Serial.write(i | (pins[i].is_analog ? 0x80 : 0x00) | (pins[i].has_pullup ? 0x40 : 0x00));
-        Serial.write(pins[i].identifier);
-        Serial.write(0);
-      }
-      Serial.write(0x82);
-      Serial.write("Arduino UNO R3 running CircuitDojo");
-      Serial.write(0);
+    else if ((~byte) & 0x80) { // if the high bit is unset
+      digitalWritePin(byte & 0b00111111, byte & 0x40);
     }
-    else if (byte == 0x81) {
-      while (Serial.available() == 0) {} // block until byte
-      int pindex = Serial.read();
-      if (pindex == -1) {
-        Serial.write(0xFE);
-      }
-      else {
-        Serial.write(0xFF);
-        pinMode(pins[pindex].physical_pin, INPUT);
-        modes[pindex] = 1;
-      }
-    }
-    else if (byte == 0x82) {
-      while (Serial.available() == 0) {} // block until byte
-      int pindex = Serial.read();
-      if (pindex == -1) {
-        Serial.write(0xFE);
+    else {
+      uint8_t args[2];
+      int len = argCount(byte);
+      for (int i = 0; i < len; i ++) {
+        while (Serial.available() == 0) {} // block until byte
+        args[i] = Serial.read();
       }
-      else {
-        Serial.write(0xFF);
-        pinMode(pins[pindex].physical_pin, OUTPUT);
-        modes[pindex] = 2;
+      if (!command(0, byte, args, len)) {
+        return;
       }
     }
-    else if (byte == 0x84) { // subscribe to updates
-      while (Serial.available() < 2) {} // block until bytes
-      subsc_wavelength = Serial.read();
-      subsc_wavelength |= Serial.read() << 8;
-      Serial.write(0xFF);
-    }
-    else if (byte == 0x86) { // write all pin values, then ACK
-      for (int i = 0; i < pinCount; i ++) {
-        if (modes[i] == 1) {
-          if (pins[i].is_analog) {
-            // TODO
-          }
-          else {
-            Serial.write(i | (digitalRead(pins[i].physical_pin) ? 0x40 : 0));
-          }
-        }
-      }
-      Serial.write(0xFF);
-    }
-    else {
-      Serial.write(0xFE); // send an error code and get out of here skoob
-      return;
-    }
   }
 }
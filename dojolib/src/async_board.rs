@@ -0,0 +1,387 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// Board, for tokio programs (build with the "async" feature).
+// a Transport is blocking, so there's still a thread reading the port, but nothing on the application's side
+// ever blocks: commands return futures that resolve when the board ACKs (or ERRORs) them, and pin changes
+// come out of a Stream. every method takes &self, so an Arc<AsyncBoard> can be shared between tasks.
+//
+// it's lighter than Board: no layouts, no recorder and no reconnecting. when the connection dies, every
+// waiting and future command fails and the pin change streams end.
+
+use crate::board::{self, Board, PinData, PinMode, PinStatus, PinType};
+use crate::connection::{Capabilities, Command, Connection, Event};
+use crate::profiles::{self, Profile};
+use crate::transport::Transport;
+use crate::{CircuitDojoError, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+const CHANGE_CAPACITY: usize = 1024; // how far a pin_changes stream can fall behind before it starts skipping
+
+#[derive(Debug, Copy, Clone)]
+pub struct PinChange {
+    pub pin: u8, // hw_id
+    pub status: PinStatus,
+    pub at: Instant, // when the worker read it, or when the board saw it if it has timestamps on
+    pub board_time: Option<u64>, // microseconds on the board's clock, with timestamps on
+}
+
+type Reply = oneshot::Sender<Result<()>>;
+
+struct PinState {
+    pins: Vec<PinData>,
+    mapped_pins_hwids: HashMap<u8, usize>,
+}
+
+impl PinState {
+    fn get_mut(&mut self, pin_num: u8) -> Result<&mut PinData> {
+        let pindex = self
+            .mapped_pins_hwids
+            .get(&pin_num)
+            .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
+        Ok(&mut self.pins[*pindex])
+    }
+}
+
+pub struct AsyncBoard {
+    board_name: String,
+    min_sample: u16,
+    capabilities: Capabilities,
+    profile: Option<&'static Profile>,
+    state: Arc<Mutex<PinState>>, // shared with the worker, which keeps input statuses fresh
+    commands: mpsc::UnboundedSender<(Command, Reply)>,
+    changes: broadcast::Receiver<PinChange>, // never read, just resubscribed; the worker owns the only sender
+}
+
+impl AsyncBoard {
+    pub async fn new(transport: impl Transport + 'static) -> Result<Self> {
+        Self::connect(move || Ok((Connection::new(transport)?, None))).await
+    }
+
+    pub async fn open(port: impl Into<String>, baud: u32) -> Result<Self> {
+        // connect to a board on a serial port
+        let port = port.into();
+        Self::connect(move || {
+            let usb_id = board::usb_port(&port).map(|usb| (usb.vid, usb.pid));
+            Ok((Connection::open(port, baud)?, usb_id))
+        })
+        .await
+    }
+
+    async fn connect(
+        open: impl FnOnce() -> Result<(Connection, Option<(u16, u16)>)> + Send + 'static,
+    ) -> Result<Self> {
+        // the handshake waits for the board to boot, so it gets a blocking thread instead of the runtime
+        let (conn, board_name, min_sample, pins, capabilities, usb_id) =
+            tokio::task::spawn_blocking(move || {
+                let (mut conn, usb_id) = open()?;
+                let (board_name, min_sample, pins, capabilities) = Board::handshake(&mut conn)?;
+                Ok::<_, CircuitDojoError>((
+                    conn,
+                    board_name,
+                    min_sample,
+                    pins,
+                    capabilities,
+                    usb_id,
+                ))
+            })
+            .await
+            .map_err(|e| CircuitDojoError::SynchronizationError(e.to_string()))??;
+        let profile = profiles::identify(&board_name, usb_id);
+        let mut mapped_pins_hwids = HashMap::new();
+        for (i, pin) in pins.iter().enumerate() {
+            mapped_pins_hwids.insert(pin.hw_id, i);
+        }
+        let state = Arc::new(Mutex::new(PinState {
+            pins,
+            mapped_pins_hwids,
+        }));
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (change_tx, change_rx) = broadcast::channel(CHANGE_CAPACITY);
+        let worker_state = state.clone();
+        std::thread::spawn(move || Self::worker(conn, command_rx, change_tx, worker_state));
+        Ok(Self {
+            board_name,
+            min_sample,
+            capabilities,
+            profile,
+            state,
+            commands: command_tx,
+            changes: change_rx,
+        })
+    }
+
+    fn worker(
+        mut connection: Connection,
+        mut commands: mpsc::UnboundedReceiver<(Command, Reply)>,
+        changes: broadcast::Sender<PinChange>,
+        state: Arc<Mutex<PinState>>,
+    ) {
+        // in the same order as the connection's waiting commands, so each ACK or ERROR goes to the right future
+        let mut replies: VecDeque<Reply> = VecDeque::new();
+        let fatal = 'running: loop {
+            match connection.wait_incoming() {
+                Ok(_) | Err(CircuitDojoError::TimedOut) => {}
+                Err(e) => break 'running e,
+            }
+            let now = Instant::now();
+            for event in connection.events() {
+                match event {
+                    Event::Ack(_) => {
+                        if let Some(reply) = replies.pop_front() {
+                            let _ = reply.send(Ok(())); // nobody waiting is fine, the future was dropped
+                        }
+                    }
+                    Event::BoardError(command) => {
+                        if let Some(reply) = replies.pop_front() {
+                            let _ = reply.send(Err(CircuitDojoError::BoardError(command)));
+                        }
+                    }
+                    Event::NoReply(command) => {
+                        if let Some(reply) = replies.pop_front() {
+                            let _ = reply.send(Err(CircuitDojoError::SynchronizationError(
+                                format!("the board's answer to {:?} got lost", command),
+                            )));
+                        }
+                    }
+                    Event::DigitalPinStateChange(pin, level) => Self::changed(
+                        &state,
+                        &changes,
+                        pin,
+                        PinStatus::DigitalInputting(level),
+                        now,
+                        None,
+                    ),
+                    Event::AnalogPinStateChange(pin, value) => Self::changed(
+                        &state,
+                        &changes,
+                        pin,
+                        PinStatus::AnalogInputting(value),
+                        now,
+                        None,
+                    ),
+                    Event::TimedDigitalPinStateChange(pin, level, at) => Self::changed(
+                        &state,
+                        &changes,
+                        pin,
+                        PinStatus::DigitalInputting(level),
+                        at.host,
+                        Some(at.micros),
+                    ),
+                    Event::TimedAnalogPinStateChange(pin, value, at) => Self::changed(
+                        &state,
+                        &changes,
+                        pin,
+                        PinStatus::AnalogInputting(value),
+                        at.host,
+                        Some(at.micros),
+                    ),
+                    _ => {}
+                }
+            }
+            loop {
+                let (command, reply) = match commands.try_recv() {
+                    Ok(next) => next,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return, // the AsyncBoard was dropped
+                };
+                let expects_reply = command.expects_reply();
+                match connection.write_command(command) {
+                    Ok(()) if expects_reply => replies.push_back(reply),
+                    Ok(()) => {
+                        let _ = reply.send(Ok(())); // nothing more to wait for
+                    }
+                    Err(CircuitDojoError::TimedOut) => {
+                        let _ = reply.send(Err(CircuitDojoError::TimedOut));
+                    }
+                    Err(e) => {
+                        replies.push_back(reply);
+                        break 'running e;
+                    }
+                }
+            }
+        };
+        // the oldest waiting command hears why; everything else just hears that we're gone
+        commands.close();
+        let mut fatal = Some(fatal);
+        for reply in replies {
+            let _ = reply.send(Err(fatal.take().unwrap_or(CircuitDojoError::NotConnected)));
+        }
+        while let Ok((_, reply)) = commands.try_recv() {
+            let _ = reply.send(Err(CircuitDojoError::NotConnected));
+        }
+    }
+
+    fn changed(
+        state: &Mutex<PinState>,
+        changes: &broadcast::Sender<PinChange>,
+        pin_num: u8,
+        status: PinStatus,
+        at: Instant,
+        board_time: Option<u64>,
+    ) {
+        let mut state = state.lock().unwrap();
+        let Ok(pin) = state.get_mut(pin_num) else {
+            return;
+        };
+        pin.status = match (pin.mode, status) {
+            // the board doesn't say which kind of input it read
+            (PinMode::InputPullup, PinStatus::DigitalInputting(level)) => {
+                PinStatus::DigitalPullupInputting(level)
+            }
+            _ => status,
+        };
+        let _ = changes.send(PinChange {
+            pin: pin_num,
+            status: pin.status,
+            at,
+            board_time,
+        }); // an error just means no streams are open
+    }
+
+    fn queue(&self, command: Command) -> Result<oneshot::Receiver<Result<()>>> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send((command, reply))
+            .map_err(|_| CircuitDojoError::NotConnected)?;
+        Ok(response)
+    }
+
+    async fn response(response: oneshot::Receiver<Result<()>>) -> Result<()> {
+        response.await.map_err(|_| CircuitDojoError::NotConnected)?
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.board_name
+    }
+
+    pub fn get_min_sample(&self) -> u16 {
+        // the shortest subscription wavelength the board claims to handle
+        self.min_sample
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        // what the board's firmware can do, as it told us during the handshake
+        &self.capabilities
+    }
+
+    pub fn profile(&self) -> Option<&'static Profile> {
+        // what we know about the board's hardware, going by its name and USB ids like Board::profile
+        self.profile
+    }
+
+    pub fn pins(&self) -> Vec<PinData> {
+        // a snapshot; statuses keep changing after this returns
+        self.state.lock().unwrap().pins.clone()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        !self.commands.is_closed()
+    }
+
+    pub fn pin_changes(&self) -> impl Stream<Item = PinChange> + Send + Unpin + 'static {
+        // every pin status the board reports from now on. a stream that falls more than CHANGE_CAPACITY
+        // changes behind skips ahead instead of holding the others up.
+        BroadcastStream::new(self.changes.resubscribe()).filter_map(|change| change.ok())
+    }
+
+    // the state changes as soon as a command is sent (and not if it can't be), like Board; the future is just for
+    // finding out whether the board went along with it.
+    // dropping one of these futures doesn't take the command back.
+
+    pub async fn set_output(&self, pin_num: u8) -> Result<()> {
+        let response = {
+            let mut state = self.state.lock().unwrap();
+            let pin = state.get_mut(pin_num)?;
+            let response = self.queue(Command::SetPinModeOutput(pin_num))?;
+            pin.mode = PinMode::Output;
+            response
+        };
+        Self::response(response).await
+    }
+
+    pub async fn set_input(&self, pin_num: u8) -> Result<()> {
+        let response = {
+            let mut state = self.state.lock().unwrap();
+            let pin = state.get_mut(pin_num)?;
+            let response = self.queue(Command::SetPinModeInput(pin_num))?;
+            pin.mode = PinMode::Input;
+            response
+        };
+        Self::response(response).await
+    }
+
+    pub async fn set_input_pullup(&self, pin_num: u8) -> Result<()> {
+        let response = {
+            let mut state = self.state.lock().unwrap();
+            let pin = state.get_mut(pin_num)?;
+            if !matches!(pin.tp, PinType::DigitalPullup) {
+                return Err(CircuitDojoError::PullupUnsupported(pin_num));
+            }
+            let response = self.queue(Command::SetPinModeInputPullup(pin_num))?;
+            pin.mode = PinMode::InputPullup;
+            response
+        };
+        Self::response(response).await
+    }
+
+    pub async fn digital_write(&self, pin_num: u8, value: bool) -> Result<()> {
+        // the board doesn't ACK digital writes, so this resolves once the command is on the wire
+        let response = {
+            let mut state = self.state.lock().unwrap();
+            let pin = state.get_mut(pin_num)?;
+            if !matches!(pin.mode, PinMode::Output) {
+                return Err(CircuitDojoError::InvalidPin(pin_num));
+            }
+            let response = self.queue(Command::SetDigitalPinValue(pin_num, value))?;
+            pin.status = PinStatus::DigitalOutputting(value);
+            response
+        };
+        Self::response(response).await
+    }
+
+    pub async fn analog_write(&self, pin_num: u8, duty: u8) -> Result<()> {
+        // drive a PWM-capable output pin with a duty cycle from 0 (always low) to 255 (always high)
+        let response = {
+            let mut state = self.state.lock().unwrap();
+            let pin = state.get_mut(pin_num)?;
+            if !pin.pwm {
+                return Err(CircuitDojoError::PwmUnsupported(pin_num));
+            }
+            if !matches!(pin.mode, PinMode::Output) {
+                return Err(CircuitDojoError::InvalidPin(pin_num));
+            }
+            let response = self.queue(Command::SetAnalogPinValue(pin_num, duty))?;
+            pin.status = PinStatus::AnalogOutputting(duty as u16);
+            response
+        };
+        Self::response(response).await
+    }
+
+    pub async fn subscribe(&self, wavelength: u16) -> Result<()> {
+        Self::response(self.queue(Command::Subscribe(wavelength))?).await
+    }
+
+    pub async fn set_timestamps(&self, on: bool) -> Result<()> {
+        // see Board::set_timestamps
+        if !self.capabilities.timestamps {
+            return Err(CircuitDojoError::Unsupported("timestamps"));
+        }
+        Self::response(self.queue(Command::SetTimestamps(on))?).await
+    }
+}
@@ -12,17 +12,29 @@ THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS”
 // a nice abstraction for dealing with circuitdojo boards
 
 use std::collections::HashMap;
-use std::io::{Read, Write};
 use std::slice::Iter;
 
+use crate::bus::{BitOrder, Bus};
+use crate::capture::{Capture, CaptureRequest, MAX_CAPTURE_PINS, MAX_SAMPLE_RATE};
 use crate::error::Result;
-use crate::{CircuitDojoError, connection::*};
+use crate::layout::{Layout, LayoutMode, PinLayout, PinRef};
+use crate::pattern::{MAX_PLAY_PINS, PATTERN_CHUNK, Pattern};
+use crate::profiles::{self, Profile};
+use crate::recorder::Recorder;
+use crate::transport::Transport;
+use crate::trigger::{Condition, MAX_PATTERN_PINS, Trigger};
+use crate::{CircuitDojoError, PROTOCOL_VERSION, connection::*};
 
+use ringbuf::HeapRb;
+use ringbuf::traits::Observer;
 use ringbuf::traits::Split;
 use ringbuf::{CachingCons, CachingProd};
-use ringbuf::{HeapRb, SharedRb};
 use ringbuf::{consumer::Consumer, producer::Producer};
+use serialport::{SerialPortType, UsbPortInfo};
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
 
 #[derive(Copy, Clone)]
 pub enum PinType {
@@ -35,6 +47,7 @@ pub enum PinType {
 pub enum PinMode {
     Unset,
     Input,
+    InputPullup, // input with the internal pullup resistor on
     Output,
 }
 
@@ -44,46 +57,446 @@ pub enum PinStatus {
     DigitalOutputting(bool),
     DigitalInputting(bool),
     DigitalPullupInputting(bool),
-    AnalogOutputting(u16),
-    AnalogInputting(u16),
+    AnalogOutputting(u16), // pwm duty cycle, 0-255
+    AnalogInputting(u16),  // raw ADC reading, 0-1023 on the UNO
 }
 
+#[derive(Clone)]
 pub struct PinData {
     pub tp: PinType,
+    pub pwm: bool, // supports analog_write
     pub mode: PinMode,
     pub hw_id: u8,
     pub ident: String,
-    pub status: PinStatus, // not guaranteed to synchronize with
-                           // PinMode or PinType
+    pub status: PinStatus, // not guaranteed to synchronize with PinMode or PinType
+    pub label: Option<String>, // a friendlier name from a layout file
+    pub locked: bool,      // a layout file has fixed this pin's mode
+}
+
+impl PinData {
+    pub fn name(&self) -> &str {
+        // the label if there is one, otherwise the board's identifier
+        self.label.as_deref().unwrap_or(&self.ident)
+    }
 }
 
 pub struct Board {
     pins: Vec<PinData>,
     board_name: String,
     min_sample: u16,
+    capabilities: Capabilities,
+    profile: Option<&'static Profile>,
     mapped_pins_hwids: HashMap<u8, usize>,
-    commands: CachingProd<Arc<HeapRb<Command>>>, // commands we're spraying to the connection
+    commands: CachingProd<Arc<HeapRb<(Command, Reply)>>>, // commands we're spraying to the connection
     // inside a worker thread
     events: CachingCons<Arc<HeapRb<BoardEvent>>>,
+    recorder: Option<Recorder>,
+    subscription: Option<u16>, // the last wavelength we subscribed with, so it can be restored after a reconnect
+    timestamps: bool,
+    looping: Option<Pattern>, // a pattern playing on the board until it's stopped, to start again after a reconnect
+    playback: Option<Playback>, // a pattern we're playing ourselves, for a board that can't
+    connected: bool,
+}
+
+struct Playback {
+    // the host's stand-in for pattern playback, stepped along by update()
+    pattern: Pattern,
+    started: Instant,
+    shown: u64, // how many steps in (counting every time through the table) the pins are at
 }
 
 #[derive(Debug)]
 enum BoardEvent {
-    PinState(u8, PinStatus),
+    // stamped when the worker read it, which is as close as we get to when it happened, unless the board stamped it
+    // itself (with timestamps on). then it's when the board saw it, in microseconds on its clock and lined up with ours
+    PinState(u8, PinStatus, Instant, Option<u64>),
+    PatternDone(Pattern, Option<u16>), // the board finished or stopped a pattern, leaving the pins at this step
+    ConnectionLost, // the worker is trying to reopen the port; commands are dropped until it does
+    Reconnected, // the same board is back and has been through the handshake, but remembers nothing
+    Disconnected(CircuitDojoError), // the worker hit an error it can't recover from and stopped. always the last event
+}
+
+struct Reply {
+    done: Sender<Result<()>>,
+    samples: Option<Sender<Result<Capture>>>, // for captures, which get their samples just before they're done
+}
+
+pub struct Ack(Receiver<Result<()>>);
+
+impl Ack {
+    // a command the board hasn't answered yet. dropping it is fine: the command still runs, you just don't find out how it went.
+    // digital writes are never answered (the protocol keeps them to one byte), so theirs resolves as soon as it's sent.
+
+    pub fn wait(self) -> Result<()> {
+        // block until the board answers. a refusal comes back as BoardError with the command it refused
+        self.0.recv().unwrap_or(Err(CircuitDojoError::NotConnected))
+    }
+
+    pub fn try_wait(&mut self) -> Option<Result<()>> {
+        // the answer, if there is one yet. only the first Some is meaningful
+        match self.0.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(CircuitDojoError::NotConnected)),
+        }
+    }
+
+    fn ready() -> Self {
+        // for something done without the board's say-so
+        let (done, ack) = mpsc::channel();
+        let _ = done.send(Ok(()));
+        Ack(ack)
+    }
+}
+
+pub struct Capturing {
+    // a capture the board hasn't finished sending back. like Ack, dropping it doesn't stop the capture
+    ack: Ack,
+    samples: Receiver<Result<Capture>>,
+}
+
+impl Capturing {
+    pub fn wait(self) -> Result<Capture> {
+        // block until the board has sent every sample
+        let Capturing { ack, samples } = self;
+        ack.wait()?;
+        Self::received(&samples)
+    }
+
+    pub fn try_wait(&mut self) -> Option<Result<Capture>> {
+        Some(
+            self.ack
+                .try_wait()?
+                .and_then(|()| Self::received(&self.samples)),
+        )
+    }
+
+    fn received(samples: &Receiver<Result<Capture>>) -> Result<Capture> {
+        // the board's done, so the samples are here unless some (or the end of them) got lost on the way
+        samples.try_recv().unwrap_or_else(|_| {
+            Err(CircuitDojoError::SynchronizationError(
+                "the capture got lost in transit".to_string(),
+            ))
+        })
+    }
+}
+
+pub struct Reading {
+    // a bus read the board hasn't answered yet. like Ack, dropping it is fine
+    bus: Bus,
+    capturing: Option<Capturing>, // None if we already knew the answer
+    value: u16,
+}
+
+impl Reading {
+    pub fn wait(self) -> Result<u16> {
+        match self.capturing {
+            Some(capturing) => Self::decode(&self.bus, capturing.wait()?),
+            None => Ok(self.value),
+        }
+    }
+
+    pub fn try_wait(&mut self) -> Option<Result<u16>> {
+        match &mut self.capturing {
+            Some(capturing) => Some(
+                capturing
+                    .try_wait()?
+                    .and_then(|capture| Self::decode(&self.bus, capture)),
+            ),
+            None => Some(Ok(self.value)),
+        }
+    }
+
+    fn decode(bus: &Bus, capture: Capture) -> Result<u16> {
+        let sample = capture.sample(0).unwrap_or_default();
+        bus.value(|pin| {
+            let column = capture
+                .pins()
+                .iter()
+                .position(|captured| *captured == pin)?;
+            sample.get(column).copied()
+        })
+        .ok_or_else(|| {
+            CircuitDojoError::SynchronizationError(
+                "the board sent back an empty sample".to_string(),
+            )
+        })
+    }
+}
+
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Reconnect {
+    // how to find a board again after it drops off the bus
+    port: String,
+    baud: u32,
+    usb: Option<UsbPortInfo>, // unplugging and replugging can give the board a new path, but not a new identity
+    board_name: String,
+    pins: Vec<(u8, String)>, // hw_id and ident of every pin, to make sure it's really the same board
+    capabilities: Option<Capabilities>, // and what its firmware could do, to make sure it hasn't been reflashed
+}
+
+pub(crate) fn usb_port(port: &str) -> Option<UsbPortInfo> {
+    // what's on the other end of a serial port, if it's USB
+    serialport::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|info| info.port_name == port)
+        .and_then(|info| match info.port_type {
+            SerialPortType::UsbPort(usb) => Some(usb),
+            _ => None,
+        })
+}
+
+impl Reconnect {
+    fn new(port: &str, baud: u32) -> Self {
+        Self {
+            port: port.to_string(),
+            baud,
+            usb: usb_port(port),
+            board_name: String::new(),
+            pins: vec![],
+            capabilities: None,
+        }
+    }
+
+    fn find_moved(&self) -> Option<String> {
+        // look for a port with the same vid, pid and serial number as the one we lost
+        let usb = self.usb.as_ref()?;
+        let candidates: Vec<String> = serialport::available_ports()
+            .ok()?
+            .into_iter()
+            .filter(|info| match &info.port_type {
+                SerialPortType::UsbPort(other) => {
+                    other.vid == usb.vid
+                        && other.pid == usb.pid
+                        && other.serial_number == usb.serial_number
+                }
+                _ => false,
+            })
+            .map(|info| info.port_name)
+            .collect();
+        match (candidates.len(), &usb.serial_number) {
+            (0, _) => None,
+            (1, _) | (_, Some(_)) => candidates.into_iter().next(),
+            _ => None, // two identical boards without serial numbers, and no way to tell which one is ours
+        }
+    }
+
+    fn open(&mut self) -> Result<Connection> {
+        let mut conn = match Connection::open(self.port.clone(), self.baud) {
+            Ok(conn) => conn,
+            Err(e) => {
+                let port = self.find_moved().ok_or(e)?;
+                let conn = Connection::open(port.clone(), self.baud)?;
+                self.port = port;
+                conn
+            }
+        };
+        // handshake() turns away firmware we can't talk to at all, same as the first time
+        let (board_name, _, pins, capabilities) = Board::handshake(&mut conn)?;
+        if board_name != self.board_name
+            || !pins
+                .iter()
+                .map(|pin| (pin.hw_id, &pin.ident))
+                .eq(self.pins.iter().map(|(hw_id, ident)| (*hw_id, ident)))
+        {
+            return Err(CircuitDojoError::SynchronizationError(format!(
+                "{} is now a {}, not a {}",
+                self.port, board_name, self.board_name
+            )));
+        }
+        if Some(capabilities) != self.capabilities {
+            // the same board with different firmware on it. what we'd replay might not mean the same thing any more
+            return Err(CircuitDojoError::IncompatibleFirmware(format!(
+                "{} came back with different firmware ({:?}, was {:?})",
+                self.board_name, capabilities, self.capabilities
+            )));
+        }
+        Ok(conn)
+    }
+}
+
+fn push_command(
+    commands: &mut impl Producer<Item = (Command, Reply)>,
+    command: Command,
+) -> Result<Ack> {
+    // hand a command to the worker, without panicking if it's gone or falling behind
+    let (done, ack) = mpsc::channel();
+    push_reply(
+        commands,
+        command,
+        Reply {
+            done,
+            samples: None,
+        },
+    )?;
+    Ok(Ack(ack))
+}
+
+fn push_reply(
+    commands: &mut impl Producer<Item = (Command, Reply)>,
+    command: Command,
+    reply: Reply,
+) -> Result<()> {
+    if !commands.read_is_held() {
+        return Err(CircuitDojoError::NotConnected);
+    }
+    commands
+        .try_push((command, reply))
+        .map_err(|(command, _)| {
+            CircuitDojoError::SynchronizationError(format!(
+                "command queue is full, dropped {:?}",
+                command
+            ))
+        })?;
+    Ok(())
+}
+
+fn push_pattern(
+    commands: &mut impl Producer<Item = (Command, Reply)>,
+    pattern: Pattern,
+) -> Result<Ack> {
+    // upload the table a chunk at a time, then start it. if a chunk goes missing the board refuses the rest, PLAY_PATTERN
+    // included, so its Ack is the only one worth keeping
+    for (i, chunk) in pattern.packed().chunks(PATTERN_CHUNK).enumerate() {
+        push_command(
+            commands,
+            Command::PatternData((i * PATTERN_CHUNK) as u16, chunk.to_vec()),
+        )?;
+    }
+    push_command(commands, Command::PlayPattern(pattern))
+}
+
+fn abandon(replies: impl IntoIterator<Item = Reply>) {
+    // these commands are never getting an answer from the board
+    for reply in replies {
+        let _ = reply.done.send(Err(CircuitDojoError::NotConnected));
+    }
+}
+
+fn restore(
+    commands: &mut impl Producer<Item = (Command, Reply)>,
+    pins: &[PinData],
+    subscription: Option<u16>,
+    timestamps: bool,
+    looping: Option<&Pattern>,
+) -> Result<()> {
+    // a freshly reset board has every pin floating, so put back everything we've asked it for
+    for pin in pins {
+        let mode = match pin.mode {
+            PinMode::Unset => continue,
+            PinMode::Input => Command::SetPinModeInput(pin.hw_id),
+            PinMode::InputPullup => Command::SetPinModeInputPullup(pin.hw_id),
+            PinMode::Output => Command::SetPinModeOutput(pin.hw_id),
+        };
+        push_command(commands, mode)?;
+        let level = match pin.status {
+            PinStatus::DigitalOutputting(value) => Command::SetDigitalPinValue(pin.hw_id, value),
+            PinStatus::AnalogOutputting(duty) => Command::SetAnalogPinValue(pin.hw_id, duty as u8),
+            _ => continue,
+        };
+        push_command(commands, level)?;
+    }
+    if let Some(wavelength) = subscription {
+        push_command(commands, Command::Subscribe(wavelength))?;
+    }
+    if timestamps {
+        push_command(commands, Command::SetTimestamps(true))?;
+    }
+    if let Some(pattern) = looping {
+        push_pattern(commands, pattern.clone())?;
+    }
+    Ok(())
 }
 
 impl Board {
-    pub fn new(port: impl AsRef<str>, baud: u32) -> Result<Self> {
-        let mut conn = Connection::new(port.as_ref(), baud)?;
+    pub fn new(transport: impl Transport + 'static) -> Result<Self> {
+        Self::from_connection(Connection::new(transport)?, None)
+    }
+
+    pub fn open(port: impl AsRef<str>, baud: u32) -> Result<Self> {
+        // connect to a board on a serial port.
+        // if the board resets or gets unplugged, it's reopened (even if it comes back on a different path)
+        // and every pin mode, output level and the subscription are put back the way they were.
+        // if it comes back with different firmware, update() returns IncompatibleFirmware and that's the end of it
+        Self::from_connection(
+            Connection::open(port.as_ref(), baud)?,
+            Some(Reconnect::new(port.as_ref(), baud)),
+        )
+    }
+
+    fn from_connection(mut conn: Connection, mut reconnect: Option<Reconnect>) -> Result<Self> {
+        let (board_name, min_sample, pins, capabilities) = Self::handshake(&mut conn)?;
+        let mut mapped_pins_hwids = HashMap::new();
+        for (i, pin) in pins.iter().enumerate() {
+            mapped_pins_hwids.insert(pin.hw_id, i);
+        }
+        let usb_id = reconnect
+            .as_ref()
+            .and_then(|reconnect| reconnect.usb.as_ref())
+            .map(|usb| (usb.vid, usb.pid));
+        let profile = profiles::identify(&board_name, usb_id);
+        if let Some(reconnect) = &mut reconnect {
+            reconnect.board_name = board_name.clone();
+            reconnect.capabilities = Some(capabilities);
+            reconnect.pins = pins
+                .iter()
+                .map(|pin| (pin.hw_id, pin.ident.clone()))
+                .collect();
+        }
+        let (command_tx, command_rx) = HeapRb::new(256).split();
+        let (event_tx, event_rx) = HeapRb::new(256).split();
+        std::thread::spawn(Self::worker(command_rx, event_tx, conn, reconnect));
+        Ok(Self {
+            min_sample,
+            board_name,
+            capabilities,
+            profile,
+            mapped_pins_hwids,
+            pins,
+            commands: command_tx,
+            events: event_rx,
+            recorder: None,
+            subscription: None,
+            timestamps: false,
+            looping: None,
+            playback: None,
+            connected: true,
+        })
+    }
+
+    pub(crate) fn handshake(
+        conn: &mut Connection,
+    ) -> Result<(String, u16, Vec<PinData>, Capabilities)> {
+        // establish and read the board's name, sampling bounds, pins and capabilities
         conn.begin()?;
         conn.write_command(Command::RequestBoardParameters)?;
         let mut board_name = None;
         let mut min_sample = None;
+        let mut capabilities = None;
         let mut pins = vec![];
         while board_name.is_none() || min_sample.is_none() {
-            conn.wait_incoming()?;
+            match conn.wait_incoming() {
+                // anything the parser chokes on this early is a dojocore too old (or too new) for us
+                Err(CircuitDojoError::SynchronizationError(e)) => {
+                    return Err(CircuitDojoError::IncompatibleFirmware(format!(
+                        "the board's description didn't make sense ({})",
+                        e
+                    )));
+                }
+                result => result?,
+            }
             for event in conn.events() {
                 match event {
+                    Event::BoardError(Command::RequestBoardParameters) => {
+                        return Err(CircuitDojoError::IncompatibleFirmware(
+                            "the board won't describe itself".to_string(),
+                        ));
+                    }
+                    Event::Capabilities(reported) => {
+                        capabilities = Some(reported);
+                    }
                     Event::SamplingBounds(bounds) => {
                         min_sample = Some(bounds);
                     }
@@ -99,143 +512,1032 @@ impl Board {
                             } else {
                                 PinType::Digital
                             },
+                            pwm: false,
                             mode: PinMode::Unset,
                             hw_id: pin_id,
                             ident: pin_name,
                             status: PinStatus::NoStatus,
+                            label: None,
+                            locked: false,
                         })
                     }
+                    Event::PinPwmCapable(pin_id) => {
+                        if let Some(pin) = pins.iter_mut().find(|pin| pin.hw_id == pin_id) {
+                            pin.pwm = true;
+                        }
+                    }
                     _ => {} // ignore all other events during setup mode
                 }
             }
         }
-        let mut mapped_pins_hwids = HashMap::new();
-        for (i, pin) in pins.iter().enumerate() {
-            mapped_pins_hwids.insert(pin.hw_id, i);
+        let capabilities = capabilities.unwrap_or_else(|| Capabilities {
+            // a v1 board, which doesn't say. everything it can do shows in its pins
+            protocol: conn.version(),
+            min_host_protocol: 1,
+            analog_in: pins.iter().any(|pin| matches!(pin.tp, PinType::Analog)),
+            pwm: pins.iter().any(|pin| pin.pwm),
+            pullups: pins
+                .iter()
+                .any(|pin| matches!(pin.tp, PinType::DigitalPullup)),
+            timestamps: false,
+            bus_writes: false,
+            max_pins: pins.len() as u16,
+            capture_bytes: 0,
+            pattern_bytes: 0,
+        });
+        if capabilities.min_host_protocol > PROTOCOL_VERSION {
+            return Err(CircuitDojoError::IncompatibleFirmware(format!(
+                "the board needs a host that speaks protocol {} or newer, but this dojolib only speaks {}",
+                capabilities.min_host_protocol, PROTOCOL_VERSION
+            )));
         }
-        let (command_tx, command_rx) = HeapRb::new(32).split();
-        let (event_tx, event_rx) = HeapRb::new(32).split();
-        std::thread::spawn(Self::worker(command_rx, event_tx, conn));
-        Ok(Self {
-            min_sample: min_sample.unwrap(),
-            board_name: board_name.unwrap(),
-            mapped_pins_hwids,
-            pins,
-            commands: command_tx,
-            events: event_rx,
-        })
+        Ok((board_name.unwrap(), min_sample.unwrap(), pins, capabilities))
     }
 
     fn worker(
-        mut commands: impl Consumer<Item = Command> + Send + 'static,
+        mut commands: impl Consumer<Item = (Command, Reply)> + Send + 'static,
         mut events: impl Producer<Item = BoardEvent> + Send + 'static,
         mut connection: Connection,
-    ) -> Box<dyn FnOnce() -> () + Send> {
+        mut reconnect: Option<Reconnect>,
+    ) -> Box<dyn FnOnce() + Send> {
         Box::new(move || {
-            loop {
-                let err = connection.wait_incoming();
-                match err {
-                    Ok(_) => {}
-                    Err(CircuitDojoError::TimedOut) => {}
-                    _ => {
-                        err.unwrap();
-                    }
-                }
-                for event in connection.events() {
-                    match event {
-                        Event::BoardError(command) => {
-                            println!("failed to {:?}, synchronization issues may occur", command);
+            // events wait here when the Board isn't calling update() fast enough to keep the ring empty
+            let mut backlog = VecDeque::new();
+            // in the same order as the connection's waiting commands, so each ACK or ERROR goes to the right Ack
+            let mut replies = VecDeque::new();
+            let fatal = 'serving: loop {
+                let Some(error) = Self::run(
+                    &mut commands,
+                    &mut events,
+                    &mut connection,
+                    &mut backlog,
+                    &mut replies,
+                ) else {
+                    return; // the Board was dropped, nobody is listening
+                };
+                abandon(replies.drain(..));
+                let Some(reconnect) = &mut reconnect else {
+                    break error; // nothing to reopen
+                };
+                backlog.push_back(BoardEvent::ConnectionLost);
+                drop(connection); // let go of the port, or we might not be able to open it again
+                connection = loop {
+                    Self::flush_events(&mut events, &mut backlog);
+                    // whatever the Board asks for in the meantime is in its pin state, which it replays once we're back
+                    abandon(commands.pop_iter().map(|(_, reply)| reply));
+                    if !commands.write_is_held() {
+                        return;
+                    }
+                    std::thread::sleep(RECONNECT_INTERVAL);
+                    match reconnect.open() {
+                        Ok(connection) => break connection,
+                        // not worth waiting for: it'll still be incompatible next time
+                        Err(error @ CircuitDojoError::IncompatibleFirmware(_)) => {
+                            break 'serving error;
+                        }
+                        Err(_) => {}
+                    }
+                };
+                backlog.push_back(BoardEvent::Reconnected);
+            };
+            // from here on the Board's commands fail with NotConnected as they're pushed, instead of waiting in the
+            // queue for a worker that's stopped reading it
+            abandon(commands.pop_iter().map(|(_, reply)| reply));
+            drop(commands);
+            // make sure the Board hears about it, for as long as there's a Board to hear
+            backlog.push_back(BoardEvent::Disconnected(fatal));
+            while events.read_is_held() {
+                Self::flush_events(&mut events, &mut backlog);
+                if backlog.is_empty() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        })
+    }
+
+    fn run(
+        commands: &mut impl Consumer<Item = (Command, Reply)>,
+        events: &mut impl Producer<Item = BoardEvent>,
+        connection: &mut Connection,
+        backlog: &mut VecDeque<BoardEvent>,
+        replies: &mut VecDeque<Reply>,
+    ) -> Option<CircuitDojoError> {
+        // shuttle commands and events until the connection fails (returning why) or the Board is dropped (returning None)
+        while commands.write_is_held() {
+            match connection.wait_incoming() {
+                Ok(_) | Err(CircuitDojoError::TimedOut) => {}
+                Err(e) => return Some(e),
+            }
+            let now = Instant::now();
+            for event in connection.events() {
+                match event {
+                    Event::Ack(_) => {
+                        if let Some(reply) = replies.pop_front() {
+                            let _ = reply.done.send(Ok(()));
+                        }
+                    }
+                    Event::BoardError(command) => {
+                        // if nobody kept the Ack, they chose not to hear about this
+                        if let Some(reply) = replies.pop_front() {
+                            let _ = reply.done.send(Err(CircuitDojoError::BoardError(command)));
                         }
-                        Event::DigitalPinStateChange(pin, state) => {
-                            events
-                                .try_push(BoardEvent::PinState(
-                                    pin,
-                                    PinStatus::DigitalInputting(state),
-                                ))
-                                .unwrap();
+                    }
+                    Event::NoReply(command) => {
+                        if let Some(reply) = replies.pop_front() {
+                            let _ = reply.done.send(Err(CircuitDojoError::SynchronizationError(
+                                format!("the board's answer to {:?} got lost", command),
+                            )));
                         }
-                        _ => {}
                     }
+                    Event::DigitalPinStateChange(pin, state) => {
+                        backlog.push_back(BoardEvent::PinState(
+                            pin,
+                            PinStatus::DigitalInputting(state),
+                            now,
+                            None,
+                        ));
+                    }
+                    Event::AnalogPinStateChange(pin, value) => {
+                        backlog.push_back(BoardEvent::PinState(
+                            pin,
+                            PinStatus::AnalogInputting(value),
+                            now,
+                            None,
+                        ));
+                    }
+                    Event::TimedDigitalPinStateChange(pin, state, at) => {
+                        backlog.push_back(BoardEvent::PinState(
+                            pin,
+                            PinStatus::DigitalInputting(state),
+                            at.host,
+                            Some(at.micros),
+                        ));
+                    }
+                    Event::TimedAnalogPinStateChange(pin, value, at) => {
+                        backlog.push_back(BoardEvent::PinState(
+                            pin,
+                            PinStatus::AnalogInputting(value),
+                            at.host,
+                            Some(at.micros),
+                        ));
+                    }
+                    Event::Captured(capture) => {
+                        // the capture's reply is the first one waiting on samples; its Ack is next in line
+                        if let Some(samples) =
+                            replies.iter().find_map(|reply| reply.samples.as_ref())
+                        {
+                            let _ = samples.send(capture);
+                        }
+                    }
+                    Event::PatternDone(pattern, step) => {
+                        backlog.push_back(BoardEvent::PatternDone(pattern, step));
+                    }
+                    _ => {}
                 }
-                for command in commands.pop_iter() {
-                    println!("running {:?}", command);
-                    let _ = connection.write_command(command);
+            }
+            Self::flush_events(events, backlog);
+            for (command, reply) in commands.pop_iter() {
+                let expects_reply = command.expects_reply();
+                match connection.write_command(command) {
+                    Ok(()) if expects_reply => replies.push_back(reply),
+                    Ok(()) => {
+                        let _ = reply.done.send(Ok(()));
+                    }
+                    Err(CircuitDojoError::TimedOut) => {
+                        let _ = reply.done.send(Err(CircuitDojoError::TimedOut));
+                    }
+                    Err(e) => {
+                        replies.push_back(reply);
+                        return Some(e);
+                    }
                 }
             }
-        })
+        }
+        None
+    }
+
+    fn flush_events(
+        events: &mut impl Producer<Item = BoardEvent>,
+        backlog: &mut VecDeque<BoardEvent>,
+    ) {
+        while let Some(event) = backlog.pop_front() {
+            if let Err(event) = events.try_push(event) {
+                backlog.push_front(event);
+                break;
+            }
+        }
     }
 
     pub fn get_name(&self) -> &str {
         &self.board_name
     }
 
-    pub fn pins(&self) -> Iter<PinData> {
+    pub fn get_min_sample(&self) -> u16 {
+        // the shortest subscription wavelength the board claims to handle
+        self.min_sample
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        // what the board's firmware can do, as it told us during the handshake
+        &self.capabilities
+    }
+
+    pub fn profile(&self) -> Option<&'static Profile> {
+        // what we know about the board's hardware, if it's one we know
+        self.profile
+    }
+
+    pub fn pins(&self) -> Iter<'_, PinData> {
         self.pins.iter()
     }
 
+    pub fn is_connected(&self) -> bool {
+        // false while a board opened with Board::open is being reconnected, and for good once the worker has stopped
+        self.connected && self.commands.read_is_held()
+    }
+
     pub fn update(&mut self) -> Result<()> {
-        // read incoming events and make changes
+        // read incoming events and make changes.
+        // if the connection died and can't be reopened, this returns the error that killed it (once);
+        // after that every command fails with NotConnected. if a pattern we're playing ourselves can't write its next
+        // step, it stops and this returns why
         for event in self.events.pop_iter() {
             match event {
-                BoardEvent::PinState(pin, state) => {
+                BoardEvent::PinState(pin_num, state, at, board_time) => {
                     let pindex = self
                         .mapped_pins_hwids
-                        .get(&pin)
-                        .ok_or(CircuitDojoError::InvalidPin(pin))?;
-                    self.pins.get_mut(*pindex).unwrap().status = state;
+                        .get(&pin_num)
+                        .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
+                    let pin = self.pins.get_mut(*pindex).unwrap();
+                    pin.status = match (pin.mode, state) {
+                        // the board doesn't say which kind of input it read
+                        (PinMode::InputPullup, PinStatus::DigitalInputting(level)) => {
+                            PinStatus::DigitalPullupInputting(level)
+                        }
+                        _ => state,
+                    };
+                    if let Some(recorder) = &mut self.recorder {
+                        recorder.record(at, board_time, pin_num, pin.status);
+                    }
+                }
+                BoardEvent::PatternDone(pattern, step) => {
+                    // the board doesn't report its outputs, so this is the first we hear of where it left them
+                    if let Some(step) = step {
+                        let now = Instant::now();
+                        for (column, pin_num) in pattern.pins.iter().enumerate() {
+                            let status =
+                                PinStatus::DigitalOutputting(pattern.level(step as usize, column));
+                            let Some(pindex) = self.mapped_pins_hwids.get(pin_num) else {
+                                continue;
+                            };
+                            self.pins[*pindex].status = status;
+                            if let Some(recorder) = &mut self.recorder {
+                                recorder.record(now, None, *pin_num, status);
+                            }
+                        }
+                    }
+                }
+                BoardEvent::ConnectionLost => {
+                    self.connected = false;
                 }
+                BoardEvent::Reconnected => {
+                    self.connected = true;
+                    restore(
+                        &mut self.commands,
+                        &self.pins,
+                        self.subscription,
+                        self.timestamps,
+                        self.looping.as_ref(),
+                    )?;
+                }
+                BoardEvent::Disconnected(error) => {
+                    self.connected = false;
+                    return Err(error);
+                }
+            }
+        }
+        self.step_playback()
+    }
+
+    fn step_playback(&mut self) -> Result<()> {
+        // move a host-played pattern along to wherever it should be by now. steps that are already over by the time
+        // we get here are skipped, so it keeps time at the cost of the odd step
+        let Some(playback) = &self.playback else {
+            return Ok(());
+        };
+        let pattern = &playback.pattern;
+        let len = pattern.steps.len() as u64;
+        let mut due = (playback.started.elapsed().as_micros() / pattern.period_us as u128) as u64;
+        let finished = pattern.repeat != 0 && due >= len * pattern.repeat as u64;
+        if finished {
+            due = len * pattern.repeat as u64 - 1;
+        }
+        if due != playback.shown {
+            let step = (due % len) as usize;
+            let writes: Vec<(u8, bool)> = pattern
+                .pins
+                .iter()
+                .enumerate()
+                .map(|(column, pin_num)| (*pin_num, pattern.level(step, column)))
+                .collect();
+            if let Err(e) = self.write_step(&writes) {
+                self.playback = None;
+                return Err(e);
+            }
+            if let Some(playback) = &mut self.playback {
+                playback.shown = due;
             }
         }
+        if finished {
+            self.playback = None;
+        }
         Ok(())
     }
 
-    pub fn set_output(&mut self, pin_num: u8) -> Result<()> {
+    fn write_step(&mut self, writes: &[(u8, bool)]) -> Result<Option<Ack>> {
+        // set the pins that aren't at their level already. the last Ack, if there was anything to write
+        let mut ack = None;
+        for (pin_num, level) in writes {
+            let pindex = self.mapped_pins_hwids[pin_num];
+            if !matches!(self.pins[pindex].status, PinStatus::DigitalOutputting(current) if current == *level)
+            {
+                ack = Some(self.digital_write(*pin_num, *level)?);
+            }
+        }
+        Ok(ack)
+    }
+
+    pub fn set_output(&mut self, pin_num: u8) -> Result<Ack> {
         let pindex = self
             .mapped_pins_hwids
             .get(&pin_num)
             .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
         let pin = self.pins.get_mut(*pindex).unwrap(); // unwrap is fine here: the index must be valid to have been returned from the mapping
+        if pin.locked {
+            return Err(CircuitDojoError::PinLocked(pin_num));
+        }
+        // analog pins can be digital outputs too, like A0-A5 on an UNO
+        let ack = push_command(&mut self.commands, Command::SetPinModeOutput(pin_num))?;
         pin.mode = PinMode::Output;
-        self.commands
-            .try_push(Command::SetPinModeOutput(pin_num))
-            .unwrap();
-        Ok(())
+        Ok(ack)
     }
 
-    pub fn set_input(&mut self, pin_num: u8) -> Result<()> {
+    pub fn set_input(&mut self, pin_num: u8) -> Result<Ack> {
         let pindex = self
             .mapped_pins_hwids
             .get(&pin_num)
             .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
         let pin = self.pins.get_mut(*pindex).unwrap(); // unwrap is fine here: the index must be valid to have been returned from the mapping
+        if pin.locked {
+            return Err(CircuitDojoError::PinLocked(pin_num));
+        }
+        let ack = push_command(&mut self.commands, Command::SetPinModeInput(pin_num))?;
         pin.mode = PinMode::Input;
-        self.commands
-            .try_push(Command::SetPinModeInput(pin_num))
-            .unwrap();
-        Ok(())
+        Ok(ack)
+    }
+
+    pub fn set_input_pullup(&mut self, pin_num: u8) -> Result<Ack> {
+        let pindex = self
+            .mapped_pins_hwids
+            .get(&pin_num)
+            .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
+        let pin = self.pins.get_mut(*pindex).unwrap(); // unwrap is fine here: the index must be valid to have been returned from the mapping
+        if pin.locked {
+            return Err(CircuitDojoError::PinLocked(pin_num));
+        }
+        if !matches!(pin.tp, PinType::DigitalPullup) {
+            return Err(CircuitDojoError::PullupUnsupported(pin_num));
+        }
+        let ack = push_command(&mut self.commands, Command::SetPinModeInputPullup(pin_num))?;
+        pin.mode = PinMode::InputPullup;
+        Ok(ack)
+    }
+
+    pub fn digital_write(&mut self, pin_num: u8, value: bool) -> Result<Ack> {
+        let pindex = self
+            .mapped_pins_hwids
+            .get(&pin_num)
+            .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
+        let pin = self.pins.get_mut(*pindex).unwrap(); // unwrap is fine here: the index must be valid to have been returned from the mapping
+        if let PinMode::Output = pin.mode {
+            let ack = push_command(
+                &mut self.commands,
+                Command::SetDigitalPinValue(pin_num, value),
+            )?;
+            pin.status = PinStatus::DigitalOutputting(value);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(Instant::now(), None, pin_num, pin.status);
+            }
+            Ok(ack)
+        } else {
+            Err(CircuitDojoError::InvalidPin(pin_num))
+        }
     }
 
-    pub fn digital_write(&mut self, pin_num: u8, value: bool) -> Result<()> {
+    pub fn analog_write(&mut self, pin_num: u8, duty: u8) -> Result<Ack> {
+        // drive a PWM-capable output pin with a duty cycle from 0 (always low) to 255 (always high)
         let pindex = self
             .mapped_pins_hwids
             .get(&pin_num)
             .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
         let pin = self.pins.get_mut(*pindex).unwrap(); // unwrap is fine here: the index must be valid to have been returned from the mapping
-        pin.status = PinStatus::DigitalOutputting(value);
+        if !pin.pwm {
+            return Err(CircuitDojoError::PwmUnsupported(pin_num));
+        }
         if let PinMode::Output = pin.mode {
-            self.commands
-                .try_push(Command::SetDigitalPinValue(pin_num, value))
-                .unwrap();
+            let ack = push_command(
+                &mut self.commands,
+                Command::SetAnalogPinValue(pin_num, duty),
+            )?;
+            pin.status = PinStatus::AnalogOutputting(duty as u16);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(Instant::now(), None, pin_num, pin.status);
+            }
+            Ok(ack)
         } else {
-            return Err(CircuitDojoError::InvalidPin(pin_num));
+            Err(CircuitDojoError::InvalidPin(pin_num))
+        }
+    }
+
+    pub fn analog_read(&self, pin_num: u8) -> Result<Option<u16>> {
+        // the latest raw reading from an analog pin in input mode, or None if the board hasn't sent one yet.
+        // 0 is 0 volts and the top of the ADC's range (1023 on an UNO) is its reference voltage; Profile::volts converts.
+        let pindex = self
+            .mapped_pins_hwids
+            .get(&pin_num)
+            .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
+        let pin = &self.pins[*pindex];
+        match (pin.tp, pin.mode) {
+            (PinType::Analog, PinMode::Input) => Ok(match pin.status {
+                PinStatus::AnalogInputting(value) => Some(value),
+                _ => None,
+            }),
+            _ => Err(CircuitDojoError::InvalidPin(pin_num)),
+        }
+    }
+
+    pub fn apply_layout(&mut self, layout: &Layout) -> Result<()> {
+        // configure the board from a layout file. this replaces any layout applied before it:
+        // labels and locks from the old one are cleared first.
+        // everything is checked before anything is sent, so a layout that doesn't fit this board changes nothing.
+        // if sending fails partway (the board's gone, say), the old labels and locks come back, but whatever pins were
+        // already set up stay that way
+        let mut resolved: Vec<(u8, &PinLayout)> = vec![];
+        for entry in &layout.pins {
+            let pin = match &entry.pin {
+                PinRef::Id(id) => {
+                    self.pins
+                        .iter()
+                        .find(|pin| pin.hw_id == *id)
+                        .ok_or_else(|| {
+                            CircuitDojoError::LayoutError(format!("no pin with id {}", id))
+                        })?
+                }
+                PinRef::Name(name) => {
+                    self.pins
+                        .iter()
+                        .find(|pin| &pin.ident == name)
+                        .ok_or_else(|| {
+                            CircuitDojoError::LayoutError(format!("no pin named {}", name))
+                        })?
+                }
+            };
+            match (entry.mode, pin.tp) {
+                (Some(LayoutMode::InputPullup), PinType::Analog | PinType::Digital) => {
+                    return Err(CircuitDojoError::LayoutError(format!(
+                        "{} has no pullup",
+                        pin.ident
+                    )));
+                }
+                (Some(LayoutMode::Output), _) => {}
+                _ => {
+                    if entry.level.is_some() {
+                        return Err(CircuitDojoError::LayoutError(format!(
+                            "{} has a level but isn't an output",
+                            pin.ident
+                        )));
+                    }
+                }
+            }
+            if resolved.iter().any(|(hw_id, _)| *hw_id == pin.hw_id) {
+                return Err(CircuitDojoError::LayoutError(format!(
+                    "{} is in the layout twice",
+                    pin.ident
+                )));
+            }
+            resolved.push((pin.hw_id, entry));
+        }
+        let old: Vec<(Option<String>, bool)> = self
+            .pins
+            .iter_mut()
+            .map(|pin| (pin.label.take(), std::mem::take(&mut pin.locked)))
+            .collect();
+        let applied = self.apply_pins(&resolved);
+        if applied.is_err() {
+            for (pin, (label, locked)) in self.pins.iter_mut().zip(old) {
+                pin.label = label;
+                pin.locked = locked;
+            }
+        }
+        applied
+    }
+
+    fn apply_pins(&mut self, resolved: &[(u8, &PinLayout)]) -> Result<()> {
+        // send a checked layout's pin modes and levels, labelling and locking each pin as it goes
+        for (hw_id, entry) in resolved.iter().copied() {
+            match entry.mode {
+                Some(LayoutMode::Input) => {
+                    self.set_input(hw_id)?;
+                }
+                Some(LayoutMode::InputPullup) => {
+                    self.set_input_pullup(hw_id)?;
+                }
+                Some(LayoutMode::Output) => {
+                    self.set_output(hw_id)?;
+                    if let Some(level) = entry.level {
+                        self.digital_write(hw_id, level)?;
+                    }
+                }
+                None => {}
+            }
+            let pin = &mut self.pins[self.mapped_pins_hwids[&hw_id]];
+            pin.label = entry.label.clone();
+            pin.locked = entry.locked;
         }
         Ok(())
     }
 
-    pub fn subscribe(&mut self, wavelength: u16) -> Result<()> {
-        self.commands
-            .try_push(Command::Subscribe(wavelength))
-            .unwrap();
+    pub fn start_recording(&mut self, capacity: usize) {
+        // start keeping every pin change, up to capacity samples (after which the oldest are dropped).
+        // pins that already have a status get it recorded right away, so the recording knows where everything started.
+        // throws away any recording that was already going.
+        let mut recorder = Recorder::new(capacity, &self.pins);
+        let now = Instant::now();
+        for pin in &self.pins {
+            if !matches!(pin.status, PinStatus::NoStatus) {
+                recorder.record(now, None, pin.hw_id, pin.status);
+            }
+        }
+        self.recorder = Some(recorder);
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recorder> {
+        self.recorder.take()
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    pub fn subscribe(&mut self, wavelength: u16) -> Result<Ack> {
+        let ack = push_command(&mut self.commands, Command::Subscribe(wavelength))?;
+        self.subscription = Some(wavelength);
+        Ok(ack)
+    }
+
+    pub fn set_timestamps(&mut self, on: bool) -> Result<Ack> {
+        // have the board stamp every pin change with its own microsecond clock instead of us stamping it when it
+        // arrives, which can be a serial timeout or two late. it also watches digital inputs continuously rather than
+        // only once per subscription wavelength, so short pulses and bounces show up. like any input reporting, that
+        // only happens while there's a subscription: with none (or subscribe(0)) the board doesn't watch anything.
+        // the stamps end up in the recorder as each sample's board_time, and its host time is lined up with them.
+        if !self.capabilities.timestamps {
+            return Err(CircuitDojoError::Unsupported("timestamps"));
+        }
+        let ack = push_command(&mut self.commands, Command::SetTimestamps(on))?;
+        self.timestamps = on;
+        Ok(ack)
+    }
+
+    pub fn capture(
+        &mut self,
+        pins: &[u8],
+        sample_rate: u32,
+        sample_count: usize,
+        trigger: Trigger,
+    ) -> Result<Capturing> {
+        // have the board sample some digital pins sample_rate times a second (up to MAX_SAMPLE_RATE, and as near as it
+        // can; Capture::period says how near it got) into its own RAM, then send them all back. it doesn't do anything
+        // else while it's at it: no subscription updates, and commands wait until it's done.
+        // the board's RAM is small, so pins * sample_count can't be more than 8 * Capabilities::capture_bytes.
+        // with a trigger, trigger.pre of the samples come from before it fired and the rest from after. its pins don't
+        // have to be among the captured ones, and a Threshold trigger's has to be an analog pin
+        if self.capabilities.capture_bytes == 0 {
+            return Err(CircuitDojoError::Unsupported("capture"));
+        }
+        if pins.is_empty() || pins.len() > MAX_CAPTURE_PINS {
+            return Err(CircuitDojoError::InvalidCapture(format!(
+                "can capture 1 to {} pins, not {}",
+                MAX_CAPTURE_PINS,
+                pins.len()
+            )));
+        }
+        for pin_num in pins {
+            let pindex = self
+                .mapped_pins_hwids
+                .get(pin_num)
+                .ok_or(CircuitDojoError::InvalidPin(*pin_num))?;
+            if let PinType::Analog = self.pins[*pindex].tp {
+                return Err(CircuitDojoError::InvalidPin(*pin_num)); // captures are digital only
+            }
+        }
+        let room = self.capabilities.capture_bytes as usize * 8 / pins.len();
+        if sample_count == 0 || sample_count > room.min(u16::MAX as usize) {
+            return Err(CircuitDojoError::InvalidCapture(format!(
+                "this board can only hold 1 to {} samples of {} pins",
+                room.min(u16::MAX as usize),
+                pins.len()
+            )));
+        }
+        if sample_rate == 0 || sample_rate > MAX_SAMPLE_RATE {
+            return Err(CircuitDojoError::InvalidCapture(format!(
+                "the sample rate has to be 1Hz to {}Hz, not {}Hz",
+                MAX_SAMPLE_RATE, sample_rate
+            )));
+        }
+        if trigger.pre >= sample_count {
+            return Err(CircuitDojoError::InvalidCapture(format!(
+                "{} samples from before the trigger leaves none of {} for after it",
+                trigger.pre, sample_count
+            )));
+        }
+        if let Condition::Pattern(pattern) = &trigger.condition
+            && !(1..=MAX_PATTERN_PINS).contains(&pattern.len())
+        {
+            return Err(CircuitDojoError::InvalidCapture(format!(
+                "a pattern can have 1 to {} pins, not {}",
+                MAX_PATTERN_PINS,
+                pattern.len()
+            )));
+        }
+        let wants_analog = matches!(trigger.condition, Condition::Threshold(..));
+        for pin_num in trigger.pins() {
+            let pindex = self
+                .mapped_pins_hwids
+                .get(&pin_num)
+                .ok_or(CircuitDojoError::InvalidPin(pin_num))?;
+            if matches!(self.pins[*pindex].tp, PinType::Analog) != wants_analog {
+                return Err(CircuitDojoError::InvalidPin(pin_num));
+            }
+        }
+        let request = CaptureRequest {
+            pins: pins.to_vec(),
+            period_us: 1_000_000 / sample_rate,
+            count: sample_count as u16,
+            trigger,
+        };
+        let (done, ack) = mpsc::channel();
+        let (samples, captured) = mpsc::channel();
+        push_reply(
+            &mut self.commands,
+            Command::Capture(request),
+            Reply {
+                done,
+                samples: Some(samples),
+            },
+        )?;
+        Ok(Capturing {
+            ack: Ack(ack),
+            samples: captured,
+        })
+    }
+    pub fn play_pattern(
+        &mut self,
+        pins: &[u8],
+        steps: &[u16],
+        step_period: Duration,
+        repeat: u16,
+    ) -> Result<Ack> {
+        // clock a table of levels out of some digital outputs, one step every step_period. bit i of each step is the
+        // level of pins[i], so [0b01, 0b10, 0b11, 0b00] on two pins counts to 3 and back. it goes through the table
+        // repeat times, or until stop_pattern if repeat is 0, and replaces whatever pattern was playing.
+        // the board plays it by itself if its dojocore can (see Capabilities::pattern_bytes), so the timing doesn't
+        // depend on USB and carries on through captures, and the pins' statuses catch up once it's done. otherwise
+        // we play it here instead, from update(), which then has to be called at least once a step_period
+        if pins.is_empty() || pins.len() > MAX_PLAY_PINS {
+            return Err(CircuitDojoError::InvalidPattern(format!(
+                "can play 1 to {} pins, not {}",
+                MAX_PLAY_PINS,
+                pins.len()
+            )));
+        }
+        for pin_num in pins {
+            let pindex = self
+                .mapped_pins_hwids
+                .get(pin_num)
+                .ok_or(CircuitDojoError::InvalidPin(*pin_num))?;
+            let pin = &self.pins[*pindex];
+            if !matches!(
+                (pin.tp, pin.mode),
+                (PinType::Digital | PinType::DigitalPullup, PinMode::Output)
+            ) {
+                return Err(CircuitDojoError::InvalidPin(*pin_num)); // digital outputs only
+            }
+        }
+        if steps.is_empty() || steps.len() >= u16::MAX as usize {
+            return Err(CircuitDojoError::InvalidPattern(format!(
+                "a pattern can have 1 to {} steps, not {}",
+                u16::MAX - 1,
+                steps.len()
+            )));
+        }
+        let period_us = step_period.as_micros().min(u32::MAX as u128) as u32;
+        if period_us == 0 {
+            return Err(CircuitDojoError::InvalidPattern(
+                "the step period has to be at least a microsecond".to_string(),
+            ));
+        }
+        let pattern = Pattern {
+            pins: pins.to_vec(),
+            steps: steps.to_vec(),
+            period_us,
+            repeat,
+        };
+        if self.capabilities.pattern_bytes == 0 {
+            let writes: Vec<(u8, bool)> = pins
+                .iter()
+                .enumerate()
+                .map(|(column, pin_num)| (*pin_num, pattern.level(0, column)))
+                .collect();
+            self.playback = Some(Playback {
+                pattern,
+                started: Instant::now(),
+                shown: 0,
+            });
+            return match self.write_step(&writes)? {
+                Some(ack) => Ok(ack),
+                None => Ok(Ack::ready()), // the pins were already where the first step wants them
+            };
+        }
+        if pattern.packed_len() > self.capabilities.pattern_bytes as usize {
+            return Err(CircuitDojoError::InvalidPattern(format!(
+                "this board can only hold {} steps of {} pins",
+                self.capabilities.pattern_bytes as usize * 8 / pins.len(),
+                pins.len()
+            )));
+        }
+        let ack = push_pattern(&mut self.commands, pattern.clone())?;
+        self.looping = (repeat == 0).then_some(pattern);
+        Ok(ack)
+    }
+
+    pub fn stop_pattern(&mut self) -> Result<Ack> {
+        // stop the pattern where it is. the pins stay at the last step it played
+        self.looping = None;
+        if self.playback.take().is_some() || self.capabilities.pattern_bytes == 0 {
+            return Ok(Ack::ready()); // nothing for the board to do
+        }
+        push_command(&mut self.commands, Command::StopPattern)
+    }
+
+    pub fn bus(&self, names: &[&str], order: BitOrder) -> Result<Bus> {
+        // a bus out of pins by name: their layout labels, or what the board calls them
+        let pins = names
+            .iter()
+            .map(|name| {
+                self.pins
+                    .iter()
+                    .find(|pin| pin.name() == *name || pin.ident == *name)
+                    .map(|pin| pin.hw_id)
+                    .ok_or_else(|| CircuitDojoError::InvalidBus(format!("no pin named {}", name)))
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        Bus::new(&pins, order)
+    }
+
+    fn check_bus(&self, bus: &Bus) -> Result<()> {
+        // Bus::new already made sure it's 1 to MAX_BUS_PINS different pins, so just that they're digital pins we have
+        for pin_num in bus.pins() {
+            let pindex = self
+                .mapped_pins_hwids
+                .get(pin_num)
+                .ok_or(CircuitDojoError::InvalidPin(*pin_num))?;
+            if let PinType::Analog = self.pins[*pindex].tp {
+                return Err(CircuitDojoError::InvalidPin(*pin_num));
+            }
+        }
         Ok(())
     }
+
+    pub fn write_bus(&mut self, bus: &Bus, value: u16) -> Result<Ack> {
+        // put value on the bus. the pins have to be outputs.
+        // with a recent dojocore they all change at the same moment, so whatever's on the other end never sees a
+        // value in between. older boards get a write per pin, which does the same thing with glitches
+        self.check_bus(bus)?;
+        if value > bus.max() {
+            return Err(CircuitDojoError::InvalidBus(format!(
+                "{} doesn't fit on {} pins",
+                value,
+                bus.width()
+            )));
+        }
+        for pin_num in bus.pins() {
+            if !matches!(
+                self.pins[self.mapped_pins_hwids[pin_num]].mode,
+                PinMode::Output
+            ) {
+                return Err(CircuitDojoError::InvalidPin(*pin_num));
+            }
+        }
+        let levels = bus.levels(value);
+        if !self.capabilities.bus_writes {
+            let mut ack = Ack::ready();
+            for (pin_num, level) in levels {
+                ack = self.digital_write(pin_num, level)?;
+            }
+            return Ok(ack);
+        }
+        let ack = push_command(
+            &mut self.commands,
+            Command::SetDigitalPinValues(levels.clone()),
+        )?;
+        let now = Instant::now();
+        for (pin_num, level) in levels {
+            let pin = &mut self.pins[self.mapped_pins_hwids[&pin_num]];
+            pin.status = PinStatus::DigitalOutputting(level);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(now, None, pin_num, pin.status);
+            }
+        }
+        Ok(ack)
+    }
+
+    pub fn read_bus(&mut self, bus: &Bus) -> Result<Reading> {
+        // read the value on the bus, with every pin read at the same moment (it's a one-sample capture, so the board
+        // pauses for a moment like it does for any capture). the pins can be inputs or outputs.
+        // a board that can't capture gets the pins' latest statuses instead, which come in a pin at a time
+        self.check_bus(bus)?;
+        if self.capabilities.capture_bytes == 0 {
+            let value = bus
+                .value(
+                    |pin_num| match self.pins[self.mapped_pins_hwids[&pin_num]].status {
+                        PinStatus::DigitalInputting(level)
+                        | PinStatus::DigitalPullupInputting(level)
+                        | PinStatus::DigitalOutputting(level) => Some(level),
+                        _ => None,
+                    },
+                )
+                .ok_or_else(|| {
+                    CircuitDojoError::InvalidBus(
+                        "not every pin on the bus has a reading yet".to_string(),
+                    )
+                })?;
+            return Ok(Reading {
+                bus: bus.clone(),
+                capturing: None,
+                value,
+            });
+        }
+        let capturing = self.capture(bus.pins(), 1_000_000, 1, Trigger::immediate())?;
+        Ok(Reading {
+            bus: bus.clone(),
+            capturing: Some(capturing),
+            value: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimBoard;
+    use crate::transport::MemoryTransport;
+    use std::io::{Read, Write};
+    use std::sync::{Condvar, Mutex};
+
+    // a transport that can hold the worker up mid-write, so commands pile up in the queue behind it
+    struct Gate {
+        inner: MemoryTransport,
+        shut: Arc<(Mutex<bool>, Condvar)>,
+        stuck: mpsc::Sender<()>,
+    }
+
+    impl Read for Gate {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for Gate {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let (shut, opened) = &*self.shut;
+            let mut shut = shut.lock().unwrap();
+            if *shut {
+                let _ = self.stuck.send(());
+                while *shut {
+                    shut = opened.wait(shut).unwrap();
+                }
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Transport for Gate {
+        fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+            self.inner.set_timeout(timeout)
+        }
+    }
+
+    fn labels(board: &Board) -> Vec<(Option<String>, bool)> {
+        board
+            .pins()
+            .map(|pin| (pin.label.clone(), pin.locked))
+            .collect()
+    }
+
+    #[test]
+    fn layout_with_a_pin_twice_changes_nothing() {
+        let mut board = Board::new(SimBoard::uno().spawn()).unwrap();
+        let first = Layout::from_toml(
+            "[[pin]]\npin = 0\nlabel = \"LED\"\nmode = \"output\"\nlocked = true",
+        )
+        .unwrap();
+        board.apply_layout(&first).unwrap();
+        let before = labels(&board);
+        // hw_id 1 is physical pin 4
+        let twice = Layout::from_toml(
+            "[[pin]]\npin = 1\nlabel = \"A\"\n[[pin]]\npin = \"Digital 4\"\nlabel = \"B\"",
+        )
+        .unwrap();
+        assert!(matches!(
+            board.apply_layout(&twice),
+            Err(CircuitDojoError::LayoutError(_))
+        ));
+        assert_eq!(labels(&board), before);
+        assert!(matches!(board.pins[1].mode, PinMode::Unset));
+    }
+
+    #[test]
+    fn layout_that_fails_partway_puts_the_old_labels_back() {
+        let shut = Arc::new((Mutex::new(false), Condvar::new()));
+        let (stuck, worker_stuck) = mpsc::channel();
+        let gate = Gate {
+            inner: SimBoard::uno().spawn(),
+            shut: shut.clone(),
+            stuck,
+        };
+        let mut board = Board::new(gate).unwrap();
+        let first = Layout::from_toml("[[pin]]\npin = 0\nlabel = \"LED\"\nlocked = true").unwrap();
+        board.apply_layout(&first).unwrap();
+        let before = labels(&board);
+
+        // hold the worker up on one command. the queue holds 256, and that one keeps its place until the worker
+        // gets past it, so 252 more leave room for 3
+        *shut.0.lock().unwrap() = true;
+        board.subscribe(0).unwrap();
+        worker_stuck.recv_timeout(Duration::from_secs(5)).unwrap();
+        for _ in 0..252 {
+            board.subscribe(0).unwrap();
+        }
+        // two commands a pin, so the fourth (the second pin's level) doesn't fit
+        let second = Layout::from_toml(
+            "[[pin]]\npin = 1\nlabel = \"X\"\nmode = \"output\"\nlevel = true\nlocked = true\n\
+             [[pin]]\npin = 2\nlabel = \"Y\"\nmode = \"output\"\nlevel = true\n\
+             [[pin]]\npin = 3\nlabel = \"Z\"\nmode = \"output\"\nlevel = true",
+        )
+        .unwrap();
+        assert!(matches!(
+            board.apply_layout(&second),
+            Err(CircuitDojoError::SynchronizationError(_))
+        ));
+        assert_eq!(labels(&board), before);
+        // the first pin got all the way, the second got its mode, the third got nothing
+        assert!(matches!(
+            board.pins[1].status,
+            PinStatus::DigitalOutputting(true)
+        ));
+        assert!(matches!(board.pins[2].mode, PinMode::Output));
+        assert!(matches!(board.pins[2].status, PinStatus::NoStatus));
+        assert!(matches!(board.pins[3].mode, PinMode::Unset));
+
+        *shut.0.lock().unwrap() = false;
+        shut.1.notify_all();
+    }
+
+    #[test]
+    fn capture_keeps_at_least_one_sample_for_after_the_trigger() {
+        let mut board = Board::new(SimBoard::uno().spawn()).unwrap();
+        let trigger = |pre| Trigger {
+            pre,
+            timeout: Duration::from_millis(50),
+            ..Trigger::rising(1)
+        };
+        assert!(matches!(
+            board.capture(&[1], 10_000, 8, trigger(8)),
+            Err(CircuitDojoError::InvalidCapture(_))
+        ));
+        // nothing rises, so it times out and goes ahead with the 7 it kept
+        let capture = board
+            .capture(&[1], 10_000, 8, trigger(7))
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!((capture.len(), capture.trigger_index()), (8, 7));
+        assert!(!capture.triggered());
+    }
 }
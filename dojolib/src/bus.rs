@@ -0,0 +1,192 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+// parallel buses.
+// a 4 or 8 bit bus is just that many pins with a number spread across them, but writing it a pin at a time walks the
+// bus through every value in between (0111 to 1000 is 0110, 0100 and 0000 on the way), and a counter or latch on the
+// other end sees all of them. Board::write_bus sets every pin in one command instead, and Board::read_bus reads them
+// all in one sample.
+
+use crate::capture::MAX_CAPTURE_PINS;
+use crate::error::{CircuitDojoError, Result};
+
+pub const MAX_BUS_PINS: usize = MAX_CAPTURE_PINS; // reads are one-sample captures
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    LsbFirst, // the first pin carries bit 0
+    MsbFirst, // the first pin carries the top bit
+}
+
+#[derive(Debug, Clone)]
+pub struct Bus {
+    pins: Vec<u8>, // hw_ids
+    order: BitOrder,
+}
+
+impl Bus {
+    pub fn new(pins: &[u8], order: BitOrder) -> Result<Self> {
+        // 1 to MAX_BUS_PINS different pins. Board checks they're digital pins it has when the bus is used;
+        // see Board::bus to make one out of pin names
+        if pins.is_empty() || pins.len() > MAX_BUS_PINS {
+            return Err(CircuitDojoError::InvalidBus(format!(
+                "a bus can have 1 to {} pins, not {}",
+                MAX_BUS_PINS,
+                pins.len()
+            )));
+        }
+        for (i, pin_num) in pins.iter().enumerate() {
+            if pins[..i].contains(pin_num) {
+                return Err(CircuitDojoError::InvalidBus(format!(
+                    "pin {} is on the bus twice",
+                    pin_num
+                )));
+            }
+        }
+        Ok(Self {
+            pins: pins.to_vec(),
+            order,
+        })
+    }
+
+    pub fn pins(&self) -> &[u8] {
+        &self.pins
+    }
+
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    pub fn width(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn max(&self) -> u16 {
+        // the biggest value that fits
+        (u32::MAX >> (32 - self.width() as u32)) as u16
+    }
+
+    fn bit(&self, column: usize) -> usize {
+        // which bit of the value pins[column] carries
+        match self.order {
+            BitOrder::LsbFirst => column,
+            BitOrder::MsbFirst => self.pins.len() - 1 - column,
+        }
+    }
+
+    pub fn levels(&self, value: u16) -> Vec<(u8, bool)> {
+        // every pin and the level it has to be at for the bus to read value
+        self.pins
+            .iter()
+            .enumerate()
+            .map(|(column, pin)| (*pin, value & (1 << self.bit(column)) != 0))
+            .collect()
+    }
+
+    pub fn value(&self, level: impl Fn(u8) -> Option<bool>) -> Option<u16> {
+        // put the value back together from each pin's level, or None if a pin doesn't have one
+        let mut value = 0;
+        for (column, pin) in self.pins.iter().enumerate() {
+            if level(*pin)? {
+                value |= 1 << self.bit(column);
+            }
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::layout::Layout;
+    use crate::sim::{SimBoard, Wire};
+
+    fn looped_back() -> Board {
+        // a simulated UNO with hw_ids 1-3 (physical 4-6) jumpered to hw_ids 4-6 (physical 7-9)
+        let mut sim = SimBoard::uno();
+        for pin in 4..=6 {
+            sim = sim.wire(Wire::Tie(pin, pin + 3));
+        }
+        let mut board = Board::new(sim.spawn()).unwrap();
+        for pin in 1..=3 {
+            board.set_output(pin).unwrap().wait().unwrap();
+            board.set_input(pin + 3).unwrap().wait().unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn order_picks_which_end_the_first_pin_is() {
+        let mut board = looped_back();
+        let lsb = Bus::new(&[1, 2, 3], BitOrder::LsbFirst).unwrap();
+        let msb = Bus::new(&[1, 2, 3], BitOrder::MsbFirst).unwrap();
+        let lsb_in = Bus::new(&[4, 5, 6], BitOrder::LsbFirst).unwrap();
+        let msb_in = Bus::new(&[4, 5, 6], BitOrder::MsbFirst).unwrap();
+        assert_eq!(lsb.levels(0b110), [(1, false), (2, true), (3, true)]);
+        assert_eq!(msb.levels(0b110), [(1, true), (2, true), (3, false)]);
+        board.write_bus(&lsb, 0b110).unwrap().wait().unwrap();
+        assert_eq!(board.read_bus(&lsb_in).unwrap().wait().unwrap(), 0b110);
+        assert_eq!(board.read_bus(&msb_in).unwrap().wait().unwrap(), 0b011);
+        board.write_bus(&msb, 0b001).unwrap().wait().unwrap();
+        assert_eq!(board.read_bus(&lsb_in).unwrap().wait().unwrap(), 0b100);
+        assert_eq!(board.read_bus(&msb_in).unwrap().wait().unwrap(), 0b001);
+    }
+
+    #[test]
+    fn values_wider_than_the_bus_are_refused() {
+        let mut board = looped_back();
+        let bus = Bus::new(&[1, 2, 3], BitOrder::LsbFirst).unwrap();
+        assert_eq!(bus.max(), 7);
+        board.write_bus(&bus, 7).unwrap().wait().unwrap();
+        assert!(matches!(
+            board.write_bus(&bus, 8),
+            Err(CircuitDojoError::InvalidBus(_))
+        ));
+        let widest: Vec<u8> = (0..MAX_BUS_PINS as u8).collect();
+        assert_eq!(
+            Bus::new(&widest, BitOrder::MsbFirst).unwrap().max(),
+            u16::MAX
+        );
+        let too_wide: Vec<u8> = (0..=MAX_BUS_PINS as u8).collect();
+        assert!(matches!(
+            Bus::new(&too_wide, BitOrder::MsbFirst),
+            Err(CircuitDojoError::InvalidBus(_))
+        ));
+        assert!(matches!(
+            Bus::new(&[], BitOrder::MsbFirst),
+            Err(CircuitDojoError::InvalidBus(_))
+        ));
+    }
+
+    #[test]
+    fn pins_can_only_be_on_a_bus_once() {
+        assert!(matches!(
+            Bus::new(&[1, 2, 1], BitOrder::LsbFirst),
+            Err(CircuitDojoError::InvalidBus(_))
+        ));
+        // the same pin by its label and by its name
+        let mut board = looped_back();
+        board
+            .apply_layout(&Layout::from_toml("[[pin]]\npin = 1\nlabel = \"CLK\"").unwrap())
+            .unwrap();
+        let name = board
+            .pins()
+            .find(|pin| pin.hw_id == 1)
+            .unwrap()
+            .ident
+            .clone();
+        assert!(matches!(
+            board.bus(&["CLK", &name], BitOrder::LsbFirst),
+            Err(CircuitDojoError::InvalidBus(_))
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+// burst captures.
+// subscriptions sample every few milliseconds at best and send each change on its own, which is far too slow to see
+// anything interesting. a capture has the board sample a handful of digital pins as fast as it can into its own RAM,
+// and only send the whole block back once it's done, which makes it a (very) basic logic analyzer for short windows.
+//
+// with a trigger, the board samples into its buffer round and round, keeping the last trigger.pre samples, until the
+// trigger fires; then it fills the rest of the buffer and stops.
+//
+// on the wire, the samples are packed as a stream of bits: sample 0's pins in the order they were asked for, then
+// sample 1's, and so on, least significant bit first.
+
+use crate::trigger::Trigger;
+use std::time::Duration;
+
+pub const MAX_CAPTURE_PINS: usize = 16;
+pub const MAX_SAMPLE_RATE: u32 = 1_000_000; // the board counts the sample period in whole microseconds
+
+#[derive(Debug, Clone)]
+pub struct CaptureRequest {
+    pub pins: Vec<u8>, // hw_ids
+    pub period_us: u32,
+    pub count: u16,
+    pub trigger: Trigger,
+}
+
+impl CaptureRequest {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        // PERIOD_US (u32 LE), COUNT (u16 LE), PIN_COUNT, PINS, TRIGGER
+        let mut payload = self.period_us.to_le_bytes().to_vec();
+        payload.extend_from_slice(&self.count.to_le_bytes());
+        payload.push(self.pins.len() as u8);
+        payload.extend_from_slice(&self.pins);
+        payload.extend(self.trigger.encode());
+        payload
+    }
+
+    pub(crate) fn packed_len(&self) -> usize {
+        // how many bytes the board sends back
+        (self.pins.len() * self.count as usize).div_ceil(8)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pins: Vec<u8>,
+    levels: Vec<bool>, // every pin's level in the first sample, then every pin's level in the second...
+    period: Duration,
+    trigger_index: usize,
+    triggered: bool,
+}
+
+impl Capture {
+    pub(crate) fn unpack(
+        request: &CaptureRequest,
+        packed: &[u8],
+        elapsed_us: u32,
+        taken: u32,
+        triggered: bool,
+    ) -> Self {
+        // the board took `taken` samples in elapsed_us (first to last, as its clock saw it), counting the ones it
+        // threw away waiting for the trigger. if we asked for more than it could manage it just fell behind, so this
+        // is the real sample period and the requested one is only a wish
+        let levels = (0..request.pins.len() * request.count as usize)
+            .map(|bit| packed[bit / 8] & (1 << (bit % 8)) != 0)
+            .collect();
+        let intervals = (taken as u64).saturating_sub(1).max(1);
+        Self {
+            pins: request.pins.clone(),
+            levels,
+            period: Duration::from_nanos(elapsed_us as u64 * 1000 / intervals),
+            trigger_index: request.trigger.pre,
+            triggered,
+        }
+    }
+
+    pub fn pins(&self) -> &[u8] {
+        // hw_ids, in the order they were asked for; each sample has their levels in this order
+        &self.pins
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len() / self.pins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn period(&self) -> Duration {
+        // the time between samples, as the board actually managed it
+        self.period
+    }
+
+    pub fn time(&self, index: usize) -> Duration {
+        // when a sample was taken, counting from the first
+        self.period * index as u32
+    }
+
+    pub fn trigger_index(&self) -> usize {
+        // the sample the trigger fired on (or where the board gave up waiting for it); trigger.pre samples come before it
+        self.trigger_index
+    }
+
+    pub fn triggered(&self) -> bool {
+        // false if the trigger timed out and the board went ahead without it
+        self.triggered
+    }
+
+    pub fn sample(&self, index: usize) -> Option<&[bool]> {
+        self.levels
+            .get(index * self.pins.len()..(index + 1) * self.pins.len())
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &[bool]> {
+        self.levels.chunks(self.pins.len())
+    }
+
+    pub fn pin(&self, hw_id: u8) -> Option<impl Iterator<Item = bool> + '_> {
+        // one pin's levels, sample by sample
+        let column = self.pins.iter().position(|pin| *pin == hw_id)?;
+        Some(self.samples().map(move |sample| sample[column]))
+    }
+}
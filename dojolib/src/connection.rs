@@ -29,11 +29,15 @@ THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS”
 
 use crate::CircuitDojoError;
 use crate::Result;
-use crate::board::PinStatus;
-use crate::opcodes::{miso, mosi};
+use crate::capture::{Capture, CaptureRequest};
+use crate::opcodes::frame::{self, Deframer, Frame};
+use crate::opcodes::{COMPACT_PINS, capability, miso, mosi};
+use crate::pattern::Pattern;
+use crate::transport::Transport;
 use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::collections::vec_deque::Drain;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub enum Command {
@@ -42,51 +46,260 @@ pub enum Command {
     RequestBoardParameters,
     SetPinModeInput(u8),
     SetPinModeOutput(u8),
+    SetPinModeInputPullup(u8),
     RunOneSample,
     SetDigitalPinValue(u8, bool),
+    SetAnalogPinValue(u8, u8), // pwm duty cycle, 0-255
     Subscribe(u16),
+    SetTimestamps(bool),       // v2 only; check Capabilities::timestamps first
+    Capture(CaptureRequest),   // v2 only; check Capabilities::capture_bytes first
+    PatternData(u16, Vec<u8>), // v2 only, like the rest: check Capabilities::pattern_bytes first
+    PlayPattern(Pattern),      // the table has to have gone up in PatternData already
+    StopPattern,
+    SetDigitalPinValues(Vec<(u8, bool)>), // v2 only; check Capabilities::bus_writes first
+}
+
+impl Command {
+    pub fn expects_reply(&self) -> bool {
+        // the board answers every command with an ACK or ERROR, except digital writes, which are a single byte
+        // and fire-and-forget so they can be fast
+        !matches!(self, Command::SetDigitalPinValue(..))
+    }
+
+    fn encode(&self) -> (u8, Vec<u8>) {
+        // the v2 frame type and payload
+        match *self {
+            Command::PleaseEstablish => (mosi::PLEASE_ESTABLISH, vec![]),
+            Command::RequestBoardParameters => {
+                (mosi::REQUEST_BOARD_PARAMETERS, vec![frame::VERSION])
+            }
+            Command::RunOneSample => (mosi::RUN_ONE_SAMPLE, vec![]),
+            Command::SetDigitalPinValue(pin, value) => {
+                (mosi::SET_DIGITAL_PIN_VALUE, vec![pin, value as u8])
+            }
+            Command::SetAnalogPinValue(pin, duty) => (mosi::SET_ANALOG_PIN_VALUE, vec![pin, duty]),
+            Command::SetPinModeInput(pin) => (mosi::SET_PIN_MODE_INPUT, vec![pin]),
+            Command::SetPinModeOutput(pin) => (mosi::SET_PIN_MODE_OUTPUT, vec![pin]),
+            Command::SetPinModeInputPullup(pin) => (mosi::SET_PIN_MODE_INPUT_PULLUP, vec![pin]),
+            Command::Subscribe(wavelength) => (mosi::SUBSCRIBE, wavelength.to_le_bytes().to_vec()),
+            Command::SetTimestamps(on) => (mosi::SET_TIMESTAMPS, vec![on as u8]),
+            Command::Capture(ref request) => (mosi::CAPTURE, request.encode()),
+            Command::PatternData(offset, ref data) => {
+                let mut payload = offset.to_le_bytes().to_vec();
+                payload.extend_from_slice(data);
+                (mosi::PATTERN_DATA, payload)
+            }
+            Command::PlayPattern(ref pattern) => (mosi::PLAY_PATTERN, pattern.encode()),
+            Command::StopPattern => (mosi::STOP_PATTERN, vec![]),
+            Command::SetDigitalPinValues(ref levels) => {
+                let mut payload = vec![levels.len() as u8];
+                payload.extend(levels.iter().map(|(pin, _)| *pin));
+                let mut packed = vec![0; levels.len().div_ceil(8)];
+                for (i, (_, level)) in levels.iter().enumerate() {
+                    if *level {
+                        packed[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                payload.extend(packed);
+                (mosi::SET_DIGITAL_PIN_VALUES, payload)
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum Event {
     // an event from the board.
     BoardError(Command), // the board returned Error for some command; we probably want to log it, and possibly warn the user!
+    Ack(Command),        // the board accepted and ran some command
+    NoReply(Command), // v2 only: the board answered something sent after this, so this one's answer was lost in transit
     DigitalPinStateChange(u8, bool), // a digital pin's state changed
     AnalogPinStateChange(u8, u16), // an analog pin's state changed
+    TimedDigitalPinStateChange(u8, bool, BoardTime), // the same, with timestamps on
+    TimedAnalogPinStateChange(u8, u16, BoardTime),
     SamplingBounds(u16), // minimum time between samples (unenforced)
     PinDescription(u8, bool, bool, String), // description of a pin
     // pin_id, analog, pullup support, pin identifier
+    PinPwmCapable(u8), // this pin can do analog_write (follows its PinDescription)
     BoardDescription(String), // description of this board
-                              // just board_name right now
+    // just board_name right now
+    Capabilities(Capabilities), // v2 only: what the board can do (precedes its BoardDescription)
+    Captured(Result<Capture>), // the samples from a Command::Capture (precedes its Ack), or how many got lost in transit
+    PatternDone(Pattern, Option<u16>), // a pattern finished or was stopped, at this step (None if it hadn't played one)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoardTime {
+    // when the board saw something happen
+    pub micros: u64,   // on the board's clock, which starts around when it boots
+    pub host: Instant, // the same moment on our clock, as best we can tell
+}
+
+struct BoardClock {
+    // turns the board's 32-bit micros() into BoardTimes.
+    // micros() wraps every 71 minutes, so each stamp is unwrapped against the one before: it's taken to be whichever
+    // board time ending in those 32 bits is nearest to where the board's clock should be, going by how long we waited
+    // since. that counts any wraps we didn't see, and copes with a stamp a little older than the last one.
+    // lining the clocks up works off the stamp that got here fastest: a message can show up late but never early, so
+    // the smallest gap between its board time and its arrival is the closest to the truth. that gap is allowed to
+    // creep up slowly too, because the board's crystal doesn't tick at quite our rate
+    last_host: Instant,
+    micros: u64,
+    anchor: (u64, Instant), // a board time and when we think it happened
+}
+
+impl BoardClock {
+    fn new(raw: u32, now: Instant) -> Self {
+        Self {
+            last_host: now,
+            micros: raw as u64,
+            anchor: (raw as u64, now),
+        }
+    }
+
+    fn stamp(&mut self, raw: u32, now: Instant) -> BoardTime {
+        let expected = self.micros + now.duration_since(self.last_host).as_micros() as u64;
+        let guess = (expected & !0xFFFF_FFFF) | raw as u64;
+        self.micros = [
+            guess.checked_sub(1 << 32),
+            Some(guess),
+            Some(guess + (1 << 32)),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|candidate| candidate.abs_diff(expected))
+        .unwrap();
+        self.last_host = now;
+        let predicted = self.host(self.micros);
+        if now < predicted {
+            // got here faster than anything before it
+            self.anchor = (self.micros, now);
+        } else {
+            self.anchor.1 += (now - predicted) / 64;
+        }
+        BoardTime {
+            micros: self.micros,
+            host: self.host(self.micros),
+        }
+    }
+
+    fn host(&self, micros: u64) -> Instant {
+        // a board time on our clock
+        let (anchor_micros, anchor_host) = self.anchor;
+        if micros >= anchor_micros {
+            anchor_host + Duration::from_micros(micros - anchor_micros)
+        } else {
+            anchor_host - Duration::from_micros(anchor_micros - micros)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    // what the firmware can do. v1 boards don't say, so Board works it out from their pins
+    pub protocol: u8,          // the wire protocol version the board settled on
+    pub min_host_protocol: u8, // the oldest host protocol the board still talks to
+    pub analog_in: bool,
+    pub pwm: bool,
+    pub pullups: bool,
+    pub timestamps: bool,   // the board can stamp pin changes with its own clock
+    pub bus_writes: bool,   // the board can set several digital pins in one go
+    pub max_pins: u16,      // how many pins are in the board's pin table
+    pub capture_bytes: u16, // how much RAM the board has set aside for captures; 0 if it can't capture
+    pub pattern_bytes: u16, // the same for pattern tables; 0 if it can't play them
+}
+
+impl Capabilities {
+    fn decode(payload: &[u8]) -> Option<Self> {
+        let [
+            protocol,
+            min_host_protocol,
+            flags_lo,
+            flags_hi,
+            max_lo,
+            max_hi,
+            ref extra @ ..,
+        ] = *payload
+        else {
+            return None;
+        };
+        let flags = u16::from_le_bytes([flags_lo, flags_hi]);
+        Some(Self {
+            protocol,
+            min_host_protocol,
+            analog_in: flags & capability::ANALOG_IN != 0,
+            pwm: flags & capability::PWM != 0,
+            pullups: flags & capability::PULLUPS != 0,
+            timestamps: flags & capability::TIMESTAMPS != 0,
+            bus_writes: flags & capability::BUS_WRITES != 0,
+            max_pins: u16::from_le_bytes([max_lo, max_hi]),
+            capture_bytes: match *extra {
+                [lo, hi, ..] => u16::from_le_bytes([lo, hi]),
+                _ => 0,
+            },
+            pattern_bytes: match *extra {
+                [_, _, lo, hi, ..] => u16::from_le_bytes([lo, hi]),
+                _ => 0,
+            },
+        })
+    }
 }
 
 pub struct Connection {
-    port: Box<dyn serialport::SerialPort>,
-    waiting_commands: VecDeque<Command>,
+    port: Box<dyn Transport>,
+    waiting_commands: VecDeque<(u8, Command)>, // with the SEQ it was sent with (always 0 in v1)
     events: VecDeque<Event>,
+    version: u8, // 1 until begin() talks the board into framing
+    seq: u8,
+    deframer: Deframer,
+    clock: Option<BoardClock>, // from the first timestamp the board sends
+    capture: Option<(CaptureRequest, Vec<u8>, usize)>, // the capture being sent back: what we asked for, the packed
+    // samples so far, and how many bytes of them have come in
+    pattern: Option<(u8, Pattern)>, // the pattern the board is playing, with the SEQ its PATTERN_DONE will come back with
+    stopped: Option<(u8, Pattern, u8)>, // a pattern that's being stopped, with its SEQ and the SEQ of what stopped it
 }
 
 impl Connection {
-    pub fn new<'a>(port: impl Into<Cow<'a, str>>, baud: u32) -> serialport::Result<Self> {
+    pub fn new(mut port: impl Transport + 'static) -> Result<Self> {
+        port.set_timeout(Duration::from_secs(1))?; // after 1s of not receiving data when data is expected, fail!
         Ok(Self {
-            port: serialport::new(port, baud)
-                .dtr_on_open(false)
-                .timeout(std::time::Duration::from_secs(1)) // after 1s of not receiving data when data is expected, fail!
-                .open()?,
+            port: Box::new(port),
             waiting_commands: VecDeque::new(),
             events: VecDeque::new(),
+            version: 1,
+            seq: 0,
+            deframer: Deframer::default(),
+            clock: None,
+            capture: None,
+            pattern: None,
+            stopped: None,
         })
     }
 
+    pub fn version(&self) -> u8 {
+        // the protocol version begin() settled on
+        self.version
+    }
+
+    pub fn bad_frames(&self) -> usize {
+        // how many v2 frames were thrown away for failing their checksum. a number that keeps climbing means a bad cable or hub
+        self.deframer.bad_frames
+    }
+
+    pub fn open<'a>(port: impl Into<Cow<'a, str>>, baud: u32) -> Result<Self> {
+        // the usual case: a board on a serial port
+        Self::new(serialport::new(port, baud).dtr_on_open(false).open()?)
+    }
+
     pub fn block_read_byte(&mut self) -> Result<u8> {
         let mut buf = [0; 1];
-        self.port.read(&mut buf)?;
+        self.port.read_exact(&mut buf)?;
         Ok(buf[0])
     }
 
     pub fn write_byte(&mut self, byte: u8) -> Result<()> {
         let buf = [byte];
-        self.port.write(&buf)?;
+        self.port.write_all(&buf)?;
         Ok(())
     }
 
@@ -103,6 +316,31 @@ impl Connection {
     }
 
     pub fn write_command(&mut self, command: Command) -> Result<()> {
+        if let Command::Capture(request) = &command {
+            self.capture = Some((request.clone(), vec![0; request.packed_len()], 0));
+        }
+        if self.version >= 2 {
+            self.seq = self.seq.wrapping_add(1).max(1); // SEQ 0 is for messages that aren't answers
+            let (tp, payload) = command.encode();
+            self.port
+                .write_all(&frame::encode(self.seq, tp, &payload))?;
+            if matches!(
+                command,
+                Command::PatternData(..) | Command::PlayPattern(_) | Command::StopPattern
+            ) && let Some((playing, pattern)) = self.pattern.take()
+            {
+                // this stops whatever was playing. its PATTERN_DONE comes back (with the SEQ of its own PLAY) before
+                // this command's answer does, so it can't be mistaken for the next pattern's even if the SEQs collide
+                self.stopped = Some((playing, pattern, self.seq));
+            }
+            if let Command::PlayPattern(pattern) = &command {
+                self.pattern = Some((self.seq, pattern.clone()));
+            }
+            if command.expects_reply() {
+                self.waiting_commands.push_back((self.seq, command));
+            }
+            return Ok(());
+        }
         match command {
             Command::PleaseEstablish => {
                 self.write_byte(mosi::PLEASE_ESTABLISH)?;
@@ -113,9 +351,18 @@ impl Connection {
             Command::RunOneSample => {
                 self.write_byte(mosi::RUN_ONE_SAMPLE)?;
             }
-            Command::SetDigitalPinValue(pin, value) => {
+            Command::SetDigitalPinValue(pin, value) if pin < COMPACT_PINS => {
                 self.write_byte(pin | if value { 0x40 } else { 0x00 })?;
             }
+            Command::SetDigitalPinValue(pin, value) => {
+                self.port
+                    .write_all(&[mosi::SET_DIGITAL_PIN_VALUE, pin, value as u8])?;
+            }
+            Command::SetAnalogPinValue(pin, duty) => {
+                self.write_byte(mosi::SET_ANALOG_PIN_VALUE)?;
+                self.write_byte(pin)?;
+                self.write_byte(duty)?;
+            }
             Command::SetPinModeInput(pin) => {
                 self.write_byte(mosi::SET_PIN_MODE_INPUT)?;
                 self.write_byte(pin)?;
@@ -124,16 +371,43 @@ impl Connection {
                 self.write_byte(mosi::SET_PIN_MODE_OUTPUT)?;
                 self.write_byte(pin)?;
             }
+            Command::SetPinModeInputPullup(pin) => {
+                self.write_byte(mosi::SET_PIN_MODE_INPUT_PULLUP)?;
+                self.write_byte(pin)?;
+            }
             Command::Subscribe(wavelength) => {
                 self.write_byte(mosi::SUBSCRIBE)?;
-                self.port.write(&wavelength.to_le_bytes())?;
+                self.port.write_all(&wavelength.to_le_bytes())?;
+            }
+            Command::SetTimestamps(on) => {
+                // no v1 board knows this one; a current one at least says so
+                self.port.write_all(&[mosi::SET_TIMESTAMPS, on as u8])?;
+            }
+            Command::Capture(ref request) => {
+                // or this one, and it can't even tell where it ends. Board never sends it to a v1 board
+                self.write_byte(mosi::CAPTURE)?;
+                self.port.write_all(&request.encode())?;
             }
+            Command::PatternData(..)
+            | Command::PlayPattern(_)
+            | Command::StopPattern
+            | Command::SetDigitalPinValues(_) => {
+                // nor these
+                let (tp, payload) = command.encode();
+                self.write_byte(tp)?;
+                self.port.write_all(&payload)?;
+            }
+        }
+        if command.expects_reply() {
+            self.waiting_commands.push_back((0, command));
         }
-        self.waiting_commands.push_back(command);
         Ok(())
     }
 
     pub fn wait_incoming(&mut self) -> Result<()> {
+        if self.version >= 2 {
+            return self.wait_frame();
+        }
         let byte = self.block_read_byte()?;
         if byte < 128 {
             // this is a digital pin value set
@@ -145,26 +419,27 @@ impl Connection {
             miso::ERROR => {
                 // a board error does not necessarily terminate;
                 // we need to log this to the queue and proceed.
-                if let Some(command) = self.waiting_commands.pop_front() {
+                if let Some((_, command)) = self.waiting_commands.pop_front() {
                     self.events.push_back(Event::BoardError(command));
                 } else {
-                    return Err(CircuitDojoError::SynchronizationError(format!(
+                    return Err(CircuitDojoError::SynchronizationError(
                         "Unexpected ERROR: There is no command in queue. Possible board malfunction."
-                    )));
+                            .to_string(),
+                    ));
                 }
             }
             miso::ACK => {
-                // this is uninteresting. just discard.
-                if let Some(Command::PleaseEstablish) = self.waiting_commands.pop_front() {
-                    self.waiting_commands.retain(|m| {
-                        // if the board ACKs a PleaseEstablish, there's probably another PleaseEstablish
-                        // in the buffer that never got acked (because it was sent before the board booted)
-                        if let Command::PleaseEstablish = m {
-                            false
-                        } else {
-                            true
-                        }
-                    })
+                // uninteresting to most callers, but AsyncBoard resolves futures with these
+                match self.waiting_commands.pop_front() {
+                    Some((_, Command::PleaseEstablish)) => {
+                        self.waiting_commands.retain(|(_, m)| {
+                            // if the board ACKs a PleaseEstablish, there's probably another PleaseEstablish
+                            // in the buffer that never got acked (because it was sent before the board booted)
+                            !matches!(m, Command::PleaseEstablish)
+                        })
+                    }
+                    Some((_, command)) => self.events.push_back(Event::Ack(command)),
+                    None => {}
                 }
             }
             miso::SAMPLING_BOUNDS => {
@@ -183,10 +458,41 @@ impl Connection {
                     pin_name,
                 ));
             }
+            miso::PIN_DESCRIPTION_WIDE => {
+                // same flags as a PIN_DESCRIPTION, but the pin gets a byte to itself
+                let pin = self.block_read_byte()?;
+                let flags = self.block_read_byte()?;
+                let pin_name = self.block_read_nullt_string()?;
+                self.events.push_back(Event::PinDescription(
+                    pin,
+                    (flags & 0x80) != 0,
+                    (flags & 0x40) != 0,
+                    pin_name,
+                ));
+            }
+            miso::DIGITAL_PIN_STATE => {
+                // a pin too big for the packed byte
+                let mut buf = [0; 2];
+                self.port.read_exact(&mut buf)?;
+                self.events
+                    .push_back(Event::DigitalPinStateChange(buf[0], buf[1] != 0));
+            }
             miso::BOARD_DESCRIPTION => {
                 let board_name = self.block_read_nullt_string()?;
                 self.events.push_back(Event::BoardDescription(board_name));
             }
+            miso::PIN_PWM_CAPABLE => {
+                let pin = self.block_read_byte()?;
+                self.events.push_back(Event::PinPwmCapable(pin));
+            }
+            miso::ANALOG_PIN_STATE => {
+                // pin index, then the 10-bit reading as a little-endian u16
+                let pin = self.block_read_byte()?;
+                let mut buf = [0; 2];
+                self.port.read_exact(&mut buf)?;
+                self.events
+                    .push_back(Event::AnalogPinStateChange(pin, u16::from_le_bytes(buf)));
+            }
             _ => {
                 return Err(CircuitDojoError::SynchronizationError(format!(
                     "Expected control byte, got {}",
@@ -197,6 +503,173 @@ impl Connection {
         Ok(())
     }
 
+    fn wait_frame(&mut self) -> Result<()> {
+        // wait_incoming, for v2. there's no such thing as an unexpected byte here: junk just gets skipped
+        let Frame { seq, tp, payload } = loop {
+            if let Some(frame) = self.deframer.next_frame() {
+                break frame;
+            }
+            let mut buf = [0; 64];
+            let count = self.port.read(&mut buf)?;
+            if count == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            self.deframer.extend(&buf[..count]);
+        };
+        match (tp, payload.as_slice()) {
+            (miso::ACK, []) => self.answered(seq, true),
+            (miso::ERROR, []) => self.answered(seq, false),
+            (miso::DIGITAL_PIN_STATE, [pin, level]) => self
+                .events
+                .push_back(Event::DigitalPinStateChange(*pin, *level != 0)),
+            (miso::ANALOG_PIN_STATE, [pin, lo, hi]) => self.events.push_back(
+                Event::AnalogPinStateChange(*pin, u16::from_le_bytes([*lo, *hi])),
+            ),
+            (miso::SAMPLING_BOUNDS, [lo, hi]) => self
+                .events
+                .push_back(Event::SamplingBounds(u16::from_le_bytes([*lo, *hi]))),
+            (miso::PIN_DESCRIPTION, [pin_descriptor, pin_name @ ..]) => {
+                self.events.push_back(Event::PinDescription(
+                    pin_descriptor & 0x3F,
+                    (pin_descriptor & 0x80) != 0,
+                    (pin_descriptor & 0x40) != 0,
+                    String::from_utf8_lossy(pin_name).into_owned(),
+                ))
+            }
+            (miso::PIN_DESCRIPTION_WIDE, [pin, flags, pin_name @ ..]) => {
+                self.events.push_back(Event::PinDescription(
+                    *pin,
+                    (flags & 0x80) != 0,
+                    (flags & 0x40) != 0,
+                    String::from_utf8_lossy(pin_name).into_owned(),
+                ))
+            }
+            (miso::BOARD_DESCRIPTION, board_name) => self.events.push_back(
+                Event::BoardDescription(String::from_utf8_lossy(board_name).into_owned()),
+            ),
+            (miso::PIN_PWM_CAPABLE, [pin]) => self.events.push_back(Event::PinPwmCapable(*pin)),
+            (miso::DIGITAL_PIN_STATE_TIMED, [pin, level, t0, t1, t2, t3]) => {
+                let at = self.board_time([*t0, *t1, *t2, *t3]);
+                self.events
+                    .push_back(Event::TimedDigitalPinStateChange(*pin, *level != 0, at))
+            }
+            (miso::ANALOG_PIN_STATE_TIMED, [pin, lo, hi, t0, t1, t2, t3]) => {
+                let at = self.board_time([*t0, *t1, *t2, *t3]);
+                self.events.push_back(Event::TimedAnalogPinStateChange(
+                    *pin,
+                    u16::from_le_bytes([*lo, *hi]),
+                    at,
+                ))
+            }
+            (miso::CAPTURE_DATA, [lo, hi, data @ ..]) => {
+                if let Some((_, packed, received)) = &mut self.capture {
+                    let offset = u16::from_le_bytes([*lo, *hi]) as usize;
+                    if let Some(chunk) = packed.get_mut(offset..offset + data.len()) {
+                        chunk.copy_from_slice(data);
+                        *received += data.len();
+                    }
+                }
+            }
+            (miso::CAPTURE_DONE, [t0, t1, t2, t3, n0, n1, n2, n3, triggered]) => {
+                if let Some((request, packed, received)) = self.capture.take() {
+                    self.events
+                        .push_back(Event::Captured(if received == packed.len() {
+                            Ok(Capture::unpack(
+                                &request,
+                                &packed,
+                                u32::from_le_bytes([*t0, *t1, *t2, *t3]),
+                                u32::from_le_bytes([*n0, *n1, *n2, *n3]),
+                                *triggered != 0,
+                            ))
+                        } else {
+                            Err(CircuitDojoError::SynchronizationError(format!(
+                                "lost {} of {} capture bytes in transit",
+                                packed.len() - received,
+                                packed.len()
+                            )))
+                        }));
+                }
+            }
+            (miso::PATTERN_DONE, [lo, hi]) => {
+                let pattern = if self
+                    .stopped
+                    .as_ref()
+                    .is_some_and(|(playing, _, _)| *playing == seq)
+                {
+                    self.stopped.take().map(|(_, pattern, _)| pattern)
+                } else if self
+                    .pattern
+                    .as_ref()
+                    .is_some_and(|(playing, _)| *playing == seq)
+                {
+                    self.pattern.take().map(|(_, pattern)| pattern)
+                } else {
+                    None
+                };
+                if let Some(pattern) = pattern {
+                    let step = u16::from_le_bytes([*lo, *hi]);
+                    self.events.push_back(Event::PatternDone(
+                        pattern,
+                        (step != u16::MAX).then_some(step),
+                    ))
+                }
+            }
+            (miso::CAPABILITIES, payload)
+                if let Some(capabilities) = Capabilities::decode(payload) =>
+            {
+                self.events.push_back(Event::Capabilities(capabilities))
+            }
+            _ => {} // it made it past the CRC, so it isn't noise; probably something from a newer board that we don't know about
+        }
+        Ok(())
+    }
+
+    fn board_time(&mut self, raw: [u8; 4]) -> BoardTime {
+        let raw = u32::from_le_bytes(raw);
+        let now = Instant::now();
+        self.clock
+            .get_or_insert_with(|| BoardClock::new(raw, now))
+            .stamp(raw, now)
+    }
+
+    fn answered(&mut self, seq: u8, accepted: bool) {
+        // the board ACKed or ERRORed the command sent with this SEQ
+        if !self
+            .waiting_commands
+            .iter()
+            .any(|(waiting, _)| *waiting == seq)
+        {
+            return; // an answer to something we've already given up on
+        }
+        while let Some((waiting, command)) = self.waiting_commands.pop_front() {
+            if self
+                .stopped
+                .as_ref()
+                .is_some_and(|(_, _, stopper)| *stopper == waiting)
+            {
+                self.stopped = None; // its PATTERN_DONE would have come before this, so it isn't coming
+            }
+            if waiting == seq {
+                if !accepted
+                    && self
+                        .pattern
+                        .as_ref()
+                        .is_some_and(|(playing, _)| *playing == seq)
+                {
+                    self.pattern = None; // it never started
+                }
+                self.events.push_back(if accepted {
+                    Event::Ack(command)
+                } else {
+                    Event::BoardError(command)
+                });
+                return;
+            }
+            // answers come back in order, so if this one was skipped, its answer isn't coming
+            self.events.push_back(Event::NoReply(command));
+        }
+    }
+
     pub fn yoink_event(&mut self, selector: impl Fn(&Event) -> bool) -> Option<Event> {
         // if there is an event in the queue that matches selector,
         // swap remove it from the queue and return it.
@@ -225,12 +698,33 @@ impl Connection {
     }
 
     pub fn begin(&mut self) -> Result<()> {
+        // a board left in v2 by an earlier session only listens for frames, so first ask it to drop back to v1.
+        // a v1 board either ignores this or ERRORs out of its command loop, which is just as good
+        self.port
+            .write_all(&frame::encode(0, mosi::NEGOTIATE, &[1]))?;
+        self.drain()?;
+        self.establish()?;
+        // then ask for framing. a board that's never heard of it ERRORs out of its command loop,
+        // so it has to be established again
+        self.write_byte(mosi::NEGOTIATE)?;
+        self.write_byte(frame::VERSION)?;
+        if self.negotiated()? {
+            self.version = frame::VERSION;
+            self.waiting_commands.clear();
+            self.port.set_timeout(Duration::from_millis(10))?;
+            Ok(())
+        } else {
+            self.establish()
+        }
+    }
+
+    fn establish(&mut self) -> Result<()> {
+        self.port.set_timeout(Duration::from_secs(1))?;
         let mut retry_limit = 5;
         while retry_limit > 0 {
             self.write_command(Command::PleaseEstablish)?;
             if let Ok(()) = self.wait_incoming() {
-                self.port
-                    .set_timeout(std::time::Duration::from_millis(10))?;
+                self.port.set_timeout(Duration::from_millis(10))?;
                 return Ok(());
             }
             retry_limit -= 1;
@@ -238,7 +732,110 @@ impl Connection {
         Err(CircuitDojoError::TimedOut)
     }
 
-    pub fn events(&mut self) -> Drain<Event> {
+    fn drain(&mut self) -> Result<()> {
+        // throw away whatever the board was in the middle of saying, giving up after a moment if it won't stop
+        self.port.set_timeout(Duration::from_millis(50))?;
+        let deadline = std::time::Instant::now() + Duration::from_millis(250);
+        let mut buf = [0; 64];
+        while std::time::Instant::now() < deadline {
+            match self.port.read(&mut buf) {
+                Ok(0) => {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(_) => {}
+                Err(e) => match CircuitDojoError::from(e) {
+                    CircuitDojoError::TimedOut => break,
+                    e => return Err(e),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn negotiated(&mut self) -> Result<bool> {
+        // the board's answer to NEGOTIATE. pin updates from an old subscription might get in first, so skip those
+        self.port.set_timeout(Duration::from_secs(1))?;
+        loop {
+            match self.block_read_byte() {
+                Ok(miso::ACK) => return Ok(true),
+                Ok(miso::ERROR) | Err(CircuitDojoError::TimedOut) => return Ok(false),
+                Ok(_) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn events(&mut self) -> Drain<'_, Event> {
         self.events.drain(..)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRAP: u64 = 1 << 32;
+
+    fn us(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn board_clock_unwraps_past_u32_max() {
+        let t0 = Instant::now();
+        let mut clock = BoardClock::new(u32::MAX - 100, t0);
+        assert_eq!(clock.stamp(50, t0 + us(151)).micros, WRAP + 50);
+        // a whole wrap later, give or take
+        assert_eq!(
+            clock.stamp(10, t0 + us(151 + WRAP - 40)).micros,
+            2 * WRAP + 10
+        );
+    }
+
+    #[test]
+    fn board_clock_counts_wraps_it_never_saw() {
+        // three hours without a word is two and a half wraps, and the board's crystal is a couple of seconds out
+        let t0 = Instant::now();
+        let mut clock = BoardClock::new(1000, t0);
+        let board = 1000 + 3 * 3600 * 1_000_000 + 2_000_000u64;
+        let stamp = clock.stamp(board as u32, t0 + Duration::from_secs(3 * 3600));
+        assert_eq!(stamp.micros, board);
+        // and it's still right for the stamp after that
+        let stamp = clock.stamp(
+            (board + 500) as u32,
+            t0 + Duration::from_secs(3 * 3600) + us(600),
+        );
+        assert_eq!(stamp.micros, board + 500);
+    }
+
+    #[test]
+    fn board_clock_takes_stamps_out_of_order() {
+        // a stamp a little older than the last one is older, not 71 minutes newer
+        let t0 = Instant::now();
+        let mut clock = BoardClock::new(1000, t0);
+        let later = clock.stamp(5000, t0 + us(4100));
+        let earlier = clock.stamp(4000, t0 + us(4200));
+        assert_eq!(later.micros, 5000);
+        assert_eq!(earlier.micros, 4000);
+        assert!(earlier.host < later.host);
+        // the same across a wrap
+        let mut clock = BoardClock::new(u32::MAX - 10, t0);
+        assert_eq!(clock.stamp(20, t0 + us(40)).micros, WRAP + 20);
+        assert_eq!(
+            clock.stamp(u32::MAX - 5, t0 + us(50)).micros,
+            u32::MAX as u64 - 5
+        );
+    }
+
+    #[test]
+    fn board_clock_lines_up_on_the_fastest_message() {
+        let t0 = Instant::now();
+        let mut clock = BoardClock::new(0, t0 + us(900)); // the first message took 900us to get here
+        let fast = clock.stamp(1000, t0 + us(1100)); // this one only 100us
+        assert_eq!(fast.host, t0 + us(1100));
+        // slower ones after it get put back where the fast one says they happened, give or take the drift allowance
+        let slow = clock.stamp(2000, t0 + us(2900));
+        assert!(slow.host <= t0 + us(2900));
+        assert!(slow.host >= t0 + us(2100));
+    }
+}
@@ -0,0 +1,108 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// finding boards. ports() lists every serial port along with whatever USB knows about it, most likely boards first;
+// PortInfo::probe() goes further and asks the port whether there's a dojocore on the other end.
+
+use crate::Result;
+use crate::board::Board;
+use crate::connection::Connection;
+use crate::profiles::{self, Profile};
+use serialport::SerialPortType;
+use std::fmt;
+
+// USB-serial chips that clone boards (and the classic Nano) put in front of the microcontroller.
+// plenty of other gadgets use them too, so these only make a port look likely, not certain
+const USB_SERIAL_CHIPS: &[(u16, u16)] = &[
+    (0x0403, 0x6001), // FTDI FT232R
+    (0x1A86, 0x7523), // WCH CH340
+    (0x10C4, 0xEA60), // Silicon Labs CP210x
+];
+
+const ARDUINO_VIDS: &[u16] = &[0x2341, 0x2A03]; // Arduino LLC and Arduino SRL
+
+#[derive(Debug, Clone)]
+pub struct PortInfo {
+    pub name: String, // what to pass to Board::open
+    // the rest is only known for USB ports
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl PortInfo {
+    pub fn looks_like_arduino(&self) -> bool {
+        // whether this is probably a board, as opposed to a built-in serial port, a modem or a bluetooth link
+        let (Some(vid), Some(pid)) = (self.vid, self.pid) else {
+            return false;
+        };
+        ARDUINO_VIDS.contains(&vid)
+            || profiles::by_usb_id(vid, pid).is_some()
+            || USB_SERIAL_CHIPS.contains(&(vid, pid))
+            || self
+                .manufacturer
+                .as_ref()
+                .is_some_and(|manufacturer| manufacturer.to_lowercase().contains("arduino"))
+    }
+
+    pub fn profile(&self) -> Option<&'static Profile> {
+        // the board this is, going by its USB ids alone
+        profiles::by_usb_id(self.vid?, self.pid?)
+    }
+
+    pub fn probe(&self, baud: u32) -> Result<String> {
+        // open the port and try a dojocore handshake, giving back the board's name if it worked.
+        // this ties up the port for as long as it takes, which is several seconds when nothing answers,
+        // so keep it off any thread that has something better to do
+        let mut conn = Connection::open(self.name.as_str(), baud)?;
+        let (board_name, ..) = Board::handshake(&mut conn)?;
+        Ok(board_name)
+    }
+}
+
+impl fmt::Display for PortInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // "/dev/ttyACM0 (Arduino Uno)", or just the name if USB didn't say anything useful
+        match self.product.as_ref().or(self.manufacturer.as_ref()) {
+            Some(product) => write!(f, "{} ({})", self.name, product),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+pub fn ports() -> Result<Vec<PortInfo>> {
+    let mut ports: Vec<PortInfo> = serialport::available_ports()?
+        .into_iter()
+        .map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) => PortInfo {
+                name: port.port_name,
+                vid: Some(usb.vid),
+                pid: Some(usb.pid),
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+                serial_number: usb.serial_number,
+            },
+            _ => PortInfo {
+                name: port.port_name,
+                vid: None,
+                pid: None,
+                manufacturer: None,
+                product: None,
+                serial_number: None,
+            },
+        })
+        .collect();
+    ports.sort_by_key(|port| !port.looks_like_arduino()); // stable, so the OS's order holds within each group
+    Ok(ports)
+}
@@ -10,20 +10,32 @@ Redistribution and use in source and binary forms, with or without modification,
 THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 */
+use crate::connection::Command;
+
 #[derive(Debug)]
 pub enum CircuitDojoError {
-    BoardError,                   // the board sent 0xFE
+    BoardError(Command),          // the board sent 0xFE in reply to this command
     SynchronizationError(String), // unexpected unprocessable bytes were received
     IoError(std::io::Error),
     SerialportError(serialport::Error),
-    TimedOut, // io timeout
-    InvalidPin(u8), // tried to access a pin that does not exist
-              // or cannot be accessed
+    TimedOut,                     // io timeout
+    InvalidPin(u8),               // tried to access a pin that does not exist or cannot be accessed
+    PwmUnsupported(u8),           // tried to analog_write a pin that can't do PWM
+    PullupUnsupported(u8),        // tried to set_input_pullup a pin without a pullup resistor
+    PinLocked(u8),                // tried to change the mode of a pin a layout locked
+    LayoutError(String),          // a layout file couldn't be parsed or doesn't fit this board
+    NotConnected,                 // the connection to the board was lost; see Board::update
+    IncompatibleFirmware(String), // the board's dojocore and this dojolib can't understand each other; re-upload dojocore
+    Unsupported(&'static str),    // the board's dojocore can't do this; see Board::capabilities
+    InvalidCapture(String), // a capture the board can't take: too many pins or samples for its RAM, say
+    InvalidPattern(String), // a pattern that can't be played: too many pins, or too long for the board's RAM
+    InvalidBus(String), // a bus that doesn't fit: too many pins, the same pin twice, or a value too big for it
 }
 
 impl From<std::io::Error> for CircuitDojoError {
     fn from(error: std::io::Error) -> CircuitDojoError {
-        if let std::io::ErrorKind::TimedOut = error.kind() {
+        if let std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock = error.kind() {
+            // sockets report read timeouts as WouldBlock on some platforms
             CircuitDojoError::TimedOut
         } else {
             CircuitDojoError::IoError(error)
@@ -0,0 +1,84 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// board layout files.
+// a layout says how a board should be set up for some particular lab: which pins are inputs or outputs,
+// what they're called on the breadboard ("LED_RED", "SW1"), what level the outputs start at, and which pins
+// are locked so nobody can change their mode afterwards. they're written in TOML or JSON, like so:
+//
+//     name = "Lab 3: Traffic Light"
+//
+//     [[pin]]
+//     pin = "Digital 5"   # the pin's identifier, or its hw_id as a number
+//     label = "LED_RED"
+//     mode = "output"     # input, input_pullup, or output
+//     level = false       # starting level for outputs
+//     locked = true
+//
+// load one with Layout::load() and hand it to Board::apply_layout().
+
+use crate::{CircuitDojoError, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+pub struct Layout {
+    pub name: Option<String>,
+    #[serde(default, rename = "pin")]
+    pub pins: Vec<PinLayout>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinLayout {
+    pub pin: PinRef,
+    pub label: Option<String>,
+    pub mode: Option<LayoutMode>,
+    pub level: Option<bool>,
+    #[serde(default)]
+    pub locked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum PinRef {
+    Id(u8),       // hw_id, as the board numbers it
+    Name(String), // the board's identifier for the pin, e.g. "Digital 5"
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutMode {
+    Input,
+    InputPullup,
+    Output,
+}
+
+impl Layout {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| CircuitDojoError::LayoutError(e.to_string()))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(|e| CircuitDojoError::LayoutError(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        // .json files are read as JSON, everything else as TOML
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+}
@@ -17,18 +17,33 @@ THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS”
     validates commands, and provides a nice pin interface.
 */
 pub const DOJOLIB_VERSION: u8 = 1;
+pub const PROTOCOL_VERSION: u8 = opcodes::frame::VERSION; // the newest wire protocol this dojolib speaks
 
 pub mod connection;
 mod opcodes;
-pub use connection::Connection; // allow raw connections
+pub use connection::{BoardTime, Capabilities, Connection}; // allow raw connections
+pub mod transport;
+pub use transport::Transport;
 pub mod error;
 pub use error::{CircuitDojoError, Result};
 pub mod board;
-pub use board::Board;
-
-pub fn ports() -> Result<Vec<String>> {
-    Ok(serialport::available_ports()?
-        .into_iter()
-        .map(|m| m.port_name)
-        .collect())
-}
+pub mod capture;
+pub use board::{Ack, Board, Capturing, Reading};
+pub mod bus;
+pub use bus::{BitOrder, Bus};
+pub use capture::Capture;
+pub mod pattern;
+pub mod trigger;
+pub use trigger::{Condition, Edge, Trigger};
+pub mod discovery;
+pub use discovery::{PortInfo, ports};
+pub mod layout;
+pub mod profiles;
+pub mod sim;
+pub use layout::Layout;
+pub mod recorder;
+pub use recorder::Recorder;
+#[cfg(feature = "async")]
+pub mod async_board;
+#[cfg(feature = "async")]
+pub use async_board::AsyncBoard;
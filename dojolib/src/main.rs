@@ -20,34 +20,68 @@ fn readline() -> String {
     input.trim().to_string()
 }
 
+fn confirm(ack: Result<Ack>) {
+    // wait for the board to answer, and say so if it didn't like the command
+    if let Err(e) = ack.and_then(Ack::wait) {
+        println!("failed: {:?}", e);
+    }
+}
+
 fn connect(port: &str) {
     println!("Connecting to port {} @115200 baud", port);
-    let mut board = Board::new(port, 115200).unwrap();
+    let mut board = match Board::open(port, 115200) {
+        Ok(board) => board,
+        Err(CircuitDojoError::IncompatibleFirmware(why)) => {
+            println!(
+                "Incompatible firmware: {}. Re-upload dojocore to the board.",
+                why
+            );
+            return;
+        }
+        Err(e) => panic!("{:?}", e),
+    };
     board.subscribe(100).unwrap();
-    println!("Connected to {}", board.get_name());
+    println!(
+        "Connected to {} (protocol v{})",
+        board.get_name(),
+        board.capabilities().protocol
+    );
     loop {
         let line = readline();
-        board.update().unwrap();
+        if let Err(e) = board.update() {
+            println!("Lost connection to {}: {:?}", board.get_name(), e);
+            return;
+        }
         let mut args = line.split(" ");
-        match args.next().unwrap().as_ref() {
+        match args.next().unwrap() {
             "pins" => {
                 for pin in board.pins() {
                     println!(
-                        "* [{}] {} {}: {}",
+                        "* [{}] {}{} {}: {}",
                         pin.hw_id,
                         match pin.tp {
                             PinType::DigitalPullup => "DP WP",
                             PinType::Digital => "DP",
                             PinType::Analog => "AP",
                         },
-                        pin.ident,
+                        if pin.pwm { " PWM" } else { "" },
+                        pin.name(),
                         match pin.status {
                             PinStatus::DigitalInputting(level) => {
                                 format!("Inputting {}", if level { "HIGH" } else { "LOW" })
                             }
+                            PinStatus::DigitalPullupInputting(level) => {
+                                format!("Inputting (pullup) {}", if level { "HIGH" } else { "LOW" })
+                            }
                             PinStatus::DigitalOutputting(level) => {
                                 format!("Outputting {}", if level { "HIGH" } else { "LOW" })
                             }
+                            PinStatus::AnalogInputting(value) => {
+                                format!("Inputting {}", value)
+                            }
+                            PinStatus::AnalogOutputting(duty) => {
+                                format!("Outputting PWM {}/255", duty)
+                            }
                             _ => String::new(),
                         }
                     );
@@ -55,11 +89,111 @@ fn connect(port: &str) {
             }
             "setoutput" => {
                 let pin_num = args.next().unwrap().parse::<u8>().unwrap();
-                board.set_output(pin_num).unwrap();
+                confirm(board.set_output(pin_num));
             }
             "setinput" => {
                 let pin_num = args.next().unwrap().parse::<u8>().unwrap();
-                board.set_input(pin_num).unwrap();
+                confirm(board.set_input(pin_num));
+            }
+            "analogread" => {
+                let pin_num = args.next().unwrap().parse::<u8>().unwrap();
+                match board.analog_read(pin_num).unwrap() {
+                    Some(value) => println!("{}", value),
+                    None => println!("no reading yet"),
+                }
+            }
+            "analogwrite" => {
+                let pin_num = args.next().unwrap().parse::<u8>().unwrap();
+                let duty = args.next().unwrap().parse::<u8>().unwrap();
+                confirm(board.analog_write(pin_num, duty));
+            }
+            "setpullup" => {
+                let pin_num = args.next().unwrap().parse::<u8>().unwrap();
+                confirm(board.set_input_pullup(pin_num));
+            }
+            "layout" => {
+                let layout = Layout::load(args.next().unwrap()).unwrap();
+                board.apply_layout(&layout).unwrap();
+            }
+            "record" => {
+                let capacity = args.next().unwrap_or("100000").parse::<usize>().unwrap();
+                board.start_recording(capacity);
+            }
+            "save" => {
+                let path = args.next().unwrap();
+                match board.stop_recording() {
+                    Some(recorder) => {
+                        recorder.save(path).unwrap();
+                        println!("saved {} samples", recorder.len());
+                    }
+                    None => println!("not recording"),
+                }
+            }
+            "capture" => {
+                // capture <rate in Hz> <samples> <pin>...
+                let rate = args.next().unwrap().parse::<u32>().unwrap();
+                let count = args.next().unwrap().parse::<usize>().unwrap();
+                let pins: Vec<u8> = args.map(|pin| pin.parse::<u8>().unwrap()).collect();
+                match board
+                    .capture(&pins, rate, count, Trigger::immediate())
+                    .and_then(Capturing::wait)
+                {
+                    Ok(capture) => {
+                        println!("{} samples, {:?} apart", capture.len(), capture.period());
+                        for pin in capture.pins() {
+                            let trace: String = capture
+                                .pin(*pin)
+                                .unwrap()
+                                .map(|level| if level { '‾' } else { '_' })
+                                .collect();
+                            println!("[{}] {}", pin, trace);
+                        }
+                    }
+                    Err(e) => println!("failed: {:?}", e),
+                }
+            }
+            "clock" => {
+                // clock <rate in Hz> <cycles, 0 for forever> <pin>
+                let rate = args.next().unwrap().parse::<f64>().unwrap();
+                let cycles = args.next().unwrap().parse::<u16>().unwrap();
+                let pin_num = args.next().unwrap().parse::<u8>().unwrap();
+                let half = std::time::Duration::from_secs_f64(0.5 / rate);
+                confirm(board.play_pattern(&[pin_num], &[1, 0], half, cycles));
+                if board.capabilities().pattern_bytes == 0 && cycles != 0 {
+                    // we're playing it ourselves, and nothing calls update() while we wait for a line
+                    let until = std::time::Instant::now() + half * 2 * cycles as u32;
+                    let mut result = Ok(());
+                    while result.is_ok() && std::time::Instant::now() < until {
+                        result = board.update();
+                        std::thread::sleep(std::time::Duration::from_micros(200));
+                    }
+                    if let Err(e) = result.and_then(|()| board.update()) {
+                        println!("failed: {:?}", e);
+                    }
+                }
+            }
+            "stop" => {
+                confirm(board.stop_pattern());
+            }
+            "buswrite" => {
+                // buswrite <value> <pin>..., least significant bit first
+                let value = args.next().unwrap().parse::<u16>().unwrap();
+                let pins: Vec<u8> = args.map(|pin| pin.parse::<u8>().unwrap()).collect();
+                confirm(
+                    Bus::new(&pins, BitOrder::LsbFirst)
+                        .and_then(|bus| board.write_bus(&bus, value)),
+                );
+            }
+            "busread" => {
+                // busread <pin>..., least significant bit first
+                let pins: Vec<u8> = args.map(|pin| pin.parse::<u8>().unwrap()).collect();
+                match Bus::new(&pins, BitOrder::LsbFirst)
+                    .and_then(|bus| board.read_bus(&bus))
+                    .and_then(Reading::wait)
+                {
+                    Ok(value) => println!("{} (0x{:X})", value, value),
+                    Err(e) => println!("failed: {:?}", e),
+                }
             }
             "digitalwrite" => {
                 let pin_num = args.next().unwrap().parse::<u8>().unwrap();
@@ -68,7 +202,7 @@ fn connect(port: &str) {
                     "LOW" => false,
                     _ => panic!(),
                 };
-                board.digital_write(pin_num, value).unwrap();
+                confirm(board.digital_write(pin_num, value));
             }
             _ => {
                 println!("bad command");
@@ -78,29 +212,28 @@ fn connect(port: &str) {
 }
 
 fn main() {
-    println!("DojoLib v{DOJOLIB_VERSION} by Tyler Clarke");
+    println!("DojoLib v{DOJOLIB_VERSION} (protocol v{PROTOCOL_VERSION}) by Tyler Clarke");
     let options = ports().unwrap();
-    if options.len() == 0 {
+    if options.is_empty() {
         println!("No ports found. Abort.");
     } else if options.len() == 1 {
-        connect(&options[0]);
+        connect(&options[0].name);
     } else {
         println!("Please choose a serial port:");
         for (id, port) in options.iter().enumerate() {
-            println!("{id}. {port}");
+            // likely boards are listed first, with a star
+            let star = if port.looks_like_arduino() { "*" } else { " " };
+            println!("{id}.{star}{port}");
         }
         print!("> ");
         std::io::stdout().flush().unwrap();
         loop {
             let data = readline();
-            match data.parse::<usize>() {
-                Ok(dat) => {
-                    if let Some(port) = options.get(dat) {
-                        connect(port);
-                        break;
-                    }
-                }
-                Err(_) => {}
+            if let Ok(dat) = data.parse::<usize>()
+                && let Some(port) = options.get(dat)
+            {
+                connect(&port.name);
+                break;
             }
         }
     }
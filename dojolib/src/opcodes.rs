@@ -10,13 +10,31 @@ Redistribution and use in source and binary forms, with or without modification,
 THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 */
+// pins below this fit in the packed single-byte messages and the PIN_DESCRIPTION descriptor byte. bigger boards
+// (the Mega has 70 pins) use the wide messages for the rest, so small boards keep their compact encoding
+pub(crate) const COMPACT_PINS: u8 = 64;
+
 pub(crate) mod mosi {
     pub(crate) const PLEASE_ESTABLISH: u8 = 0xFF;
-    pub(crate) const REQUEST_BOARD_PARAMETERS: u8 = 0x80;
+    pub(crate) const REQUEST_BOARD_PARAMETERS: u8 = 0x80; // in v2, carries the protocol version the host speaks
     pub(crate) const SET_PIN_MODE_INPUT: u8 = 0x81;
     pub(crate) const SET_PIN_MODE_OUTPUT: u8 = 0x82;
+    pub(crate) const SET_PIN_MODE_INPUT_PULLUP: u8 = 0x83;
     pub(crate) const RUN_ONE_SAMPLE: u8 = 0x86;
     pub(crate) const SUBSCRIBE: u8 = 0x84;
+    pub(crate) const SET_ANALOG_PIN_VALUE: u8 = 0x85;
+    pub(crate) const NEGOTIATE: u8 = 0x87; // followed by the protocol version the host wants
+    pub(crate) const SET_DIGITAL_PIN_VALUE: u8 = 0x88; // [pin, level]; v1 only uses it for pins that don't fit in a packed byte
+    pub(crate) const SET_TIMESTAMPS: u8 = 0x89; // v2 only: [on]. while on, pin states come as the _TIMED messages
+    // v2 only: see CaptureRequest::encode. answered with CAPTURE_DATA until the samples are all sent, CAPTURE_DONE, then ACK
+    pub(crate) const CAPTURE: u8 = 0x8A;
+    // v2 only: [offset (u16 LE), table bytes...]; see Pattern. the chunks have to come in order from offset 0, and
+    // one that doesn't gets an ERROR. stops any pattern that's playing
+    pub(crate) const PATTERN_DATA: u8 = 0x8B;
+    pub(crate) const PLAY_PATTERN: u8 = 0x8C; // v2 only: see Pattern::encode. ERRORs if the table isn't all there
+    pub(crate) const STOP_PATTERN: u8 = 0x8D; // v2 only
+    // v2 only: [count, pins..., levels (packed, least significant bit first)]. sets every pin at once; see Bus
+    pub(crate) const SET_DIGITAL_PIN_VALUES: u8 = 0x8E;
 }
 
 pub(crate) mod miso {
@@ -25,4 +43,218 @@ pub(crate) mod miso {
     pub(crate) const SAMPLING_BOUNDS: u8 = 0x80;
     pub(crate) const PIN_DESCRIPTION: u8 = 0x81;
     pub(crate) const BOARD_DESCRIPTION: u8 = 0x82;
+    pub(crate) const ANALOG_PIN_STATE: u8 = 0x83;
+    pub(crate) const PIN_PWM_CAPABLE: u8 = 0x84;
+    pub(crate) const DIGITAL_PIN_STATE: u8 = 0x85; // [pin, level]; v1 only uses it for pins that don't fit in a packed byte
+    pub(crate) const CAPABILITIES: u8 = 0x86; // v2 only; comes right after the ACK to REQUEST_BOARD_PARAMETERS
+    pub(crate) const PIN_DESCRIPTION_WIDE: u8 = 0x87; // [pin, flags, name]; PIN_DESCRIPTION for pins that don't fit in its descriptor byte
+    // v2 only: DIGITAL_PIN_STATE and ANALOG_PIN_STATE followed by micros() when the board saw it, as a u32 LE
+    pub(crate) const DIGITAL_PIN_STATE_TIMED: u8 = 0x88;
+    pub(crate) const ANALOG_PIN_STATE_TIMED: u8 = 0x89;
+    pub(crate) const CAPTURE_DATA: u8 = 0x8A; // [offset (u16 LE), packed samples...], with the SEQ of the CAPTURE
+    // [elapsed (u32 LE), taken (u32 LE), triggered]: microseconds from the first sample to the last, how many samples
+    // that was (more than were asked for if it waited on a trigger), and 0 if the trigger timed out
+    pub(crate) const CAPTURE_DONE: u8 = 0x8B;
+    // [step (u16 LE)]: a pattern finished or was stopped, leaving the pins at this step, or 0xFFFF if it hadn't played one
+    pub(crate) const PATTERN_DONE: u8 = 0x8C;
+}
+
+pub(crate) mod capability {
+    // the bitmap in a CAPABILITIES message:
+    //   PROTOCOL, MIN_HOST_PROTOCOL, FLAGS (u16 LE), MAX_PINS (u16 LE), CAPTURE_BYTES (u16 LE), PATTERN_BYTES (u16 LE)
+    // newer boards may add fields on the end, which older hosts skip. boards from before a field was added leave it
+    // off, which reads as 0
+    pub(crate) const ANALOG_IN: u16 = 0x0001;
+    pub(crate) const PWM: u16 = 0x0002;
+    pub(crate) const PULLUPS: u16 = 0x0004;
+    pub(crate) const TIMESTAMPS: u16 = 0x0008;
+    pub(crate) const BUS_WRITES: u16 = 0x0010; // SET_DIGITAL_PIN_VALUES
+}
+
+pub(crate) mod frame {
+    // protocol v2 wraps every message in a frame:
+    //   START, LEN, SEQ, TYPE, PAYLOAD (LEN bytes), CRC
+    // TYPE is the same opcode v1 would send, and PAYLOAD is whatever v1 would send after it (minus string terminators).
+    // SEQ counts up from 1 on every host command, and the board's ACK or ERROR carries the SEQ of the command it's
+    // answering; everything else the board sends has SEQ 0. CRC is CRC-8 (polynomial 0x07) over LEN through PAYLOAD.
+    // a frame with a bad CRC is thrown away and the reader picks up looking for the next START, so line noise costs
+    // one message instead of the whole session.
+    use std::collections::VecDeque;
+
+    pub(crate) const VERSION: u8 = 2;
+    pub(crate) const START: u8 = 0xA5;
+    pub(crate) const MAX_PAYLOAD: usize = 64;
+    const OVERHEAD: usize = 5; // START, LEN, SEQ, TYPE, CRC
+
+    pub(crate) fn crc8(bytes: &[u8]) -> u8 {
+        let mut crc = 0u8;
+        for byte in bytes {
+            crc ^= byte;
+            for _ in 0..8 {
+                crc = if crc & 0x80 != 0 {
+                    (crc << 1) ^ 0x07
+                } else {
+                    crc << 1
+                };
+            }
+        }
+        crc
+    }
+
+    pub(crate) fn encode(seq: u8, tp: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![START, payload.len() as u8, seq, tp];
+        frame.extend_from_slice(payload);
+        frame.push(crc8(&frame[1..]));
+        frame
+    }
+
+    pub(crate) struct Frame {
+        pub(crate) seq: u8,
+        pub(crate) tp: u8,
+        pub(crate) payload: Vec<u8>,
+    }
+
+    #[derive(Default)]
+    pub(crate) struct Deframer {
+        // bytes in, frames out
+        buf: VecDeque<u8>,
+        pub(crate) bad_frames: usize,
+    }
+
+    impl Deframer {
+        pub(crate) fn extend(&mut self, bytes: &[u8]) {
+            self.buf.extend(bytes);
+        }
+
+        pub(crate) fn next_frame(&mut self) -> Option<Frame> {
+            // the next good frame, if a whole one has come in
+            loop {
+                // anything before a START is noise, or what's left of a frame we gave up on
+                while self.buf.front().is_some_and(|byte| *byte != START) {
+                    self.buf.pop_front();
+                }
+                let len = *self.buf.get(1)? as usize;
+                if len > MAX_PAYLOAD {
+                    self.resync();
+                    continue;
+                }
+                if self.buf.len() < len + OVERHEAD {
+                    return None;
+                }
+                let frame: Vec<u8> = self.buf.range(..len + OVERHEAD).copied().collect();
+                if crc8(&frame[1..len + 4]) != frame[len + 4] {
+                    self.resync();
+                    continue;
+                }
+                self.buf.drain(..len + OVERHEAD);
+                return Some(Frame {
+                    seq: frame[2],
+                    tp: frame[3],
+                    payload: frame[4..len + 4].to_vec(),
+                });
+            }
+        }
+
+        fn resync(&mut self) {
+            // that START was really noise (or the frame behind it got mangled); look for the next one right after it
+            self.bad_frames += 1;
+            self.buf.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::frame::*;
+
+    fn frames(deframer: &mut Deframer) -> Vec<(u8, u8, Vec<u8>)> {
+        std::iter::from_fn(|| deframer.next_frame())
+            .map(|frame| (frame.seq, frame.tp, frame.payload))
+            .collect()
+    }
+
+    #[test]
+    fn crc8_matches_the_reference() {
+        // CRC-8 with polynomial 0x07, no reflection, starting from 0
+        assert_eq!(crc8(b""), 0x00);
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc8(&[0x01]), 0x07);
+        assert_eq!(crc8(&[0xFF]), 0xF3);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut deframer = Deframer::default();
+        deframer.extend(&encode(1, 0x81, &[]));
+        deframer.extend(&encode(2, 0x8A, &[0xA5, 0x00, START, 0xFF]));
+        deframer.extend(&encode(3, 0x80, &[7; MAX_PAYLOAD]));
+        assert_eq!(
+            frames(&mut deframer),
+            vec![
+                (1, 0x81, vec![]),
+                (2, 0x8A, vec![0xA5, 0x00, START, 0xFF]),
+                (3, 0x80, vec![7; MAX_PAYLOAD]),
+            ]
+        );
+        assert_eq!(deframer.bad_frames, 0);
+    }
+
+    #[test]
+    fn frames_arrive_a_byte_at_a_time() {
+        let mut deframer = Deframer::default();
+        let frame = encode(9, 0x82, &[1, 2, 3]);
+        for byte in &frame[..frame.len() - 1] {
+            deframer.extend(&[*byte]);
+            assert!(deframer.next_frame().is_none());
+        }
+        deframer.extend(&frame[frame.len() - 1..]);
+        assert_eq!(frames(&mut deframer), vec![(9, 0x82, vec![1, 2, 3])]);
+    }
+
+    #[test]
+    fn a_corrupted_byte_costs_one_frame() {
+        let mut deframer = Deframer::default();
+        let mut mangled = encode(1, 0x81, &[10, 20, 30]);
+        mangled[5] ^= 0x40;
+        deframer.extend(&mangled);
+        deframer.extend(&encode(2, 0x81, &[40]));
+        assert_eq!(frames(&mut deframer), vec![(2, 0x81, vec![40])]);
+        assert_eq!(deframer.bad_frames, 1);
+    }
+
+    #[test]
+    fn a_truncated_frame_costs_one_frame() {
+        // the second frame's bytes get read as the end of the first, which fails its CRC; then it's found again
+        let mut deframer = Deframer::default();
+        let cut = encode(1, 0x81, &[10, 20, 30, 40, 50, 60]);
+        deframer.extend(&cut[..5]);
+        deframer.extend(&encode(2, 0x81, &[1, 2]));
+        deframer.extend(&encode(3, 0x81, &[3, 4]));
+        assert_eq!(
+            frames(&mut deframer),
+            vec![(2, 0x81, vec![1, 2]), (3, 0x81, vec![3, 4])]
+        );
+        assert!(deframer.bad_frames >= 1);
+    }
+
+    #[test]
+    fn a_start_byte_in_a_mangled_payload_is_skipped() {
+        // once the frame fails its CRC, the START in its payload looks like a frame of its own for a moment
+        let mut deframer = Deframer::default();
+        let mut mangled = encode(1, 0x81, &[START, 2, 0, 0x81, 0, 0]);
+        mangled[2] ^= 0x01;
+        deframer.extend(&mangled);
+        deframer.extend(&encode(2, 0x82, &[5]));
+        assert_eq!(frames(&mut deframer), vec![(2, 0x82, vec![5])]);
+        assert!(deframer.bad_frames >= 2);
+    }
+
+    #[test]
+    fn noise_and_oversized_lengths_are_skipped() {
+        let mut deframer = Deframer::default();
+        deframer.extend(&[0x00, 0x13, START, MAX_PAYLOAD as u8 + 1, 0x55]);
+        deframer.extend(&encode(4, 0x83, &[6]));
+        assert_eq!(frames(&mut deframer), vec![(4, 0x83, vec![6])]);
+        assert_eq!(deframer.bad_frames, 1);
+    }
 }
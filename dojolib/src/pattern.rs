@@ -0,0 +1,62 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+// pattern playback.
+// a host loop of digital_writes is at the mercy of USB latency and the scheduler, so a clock or a counting sequence
+// comes out lumpy. instead the whole table goes up to the board first, and the board steps through it on its own clock.
+//
+// on the wire the table is packed the same way as capture samples: step 0's levels for the pins in the order they were
+// given, then step 1's, and so on, least significant bit first. it goes up in PATTERN_DATA chunks, in order from the
+// start, then PLAY_PATTERN says how to play it.
+
+pub const MAX_PLAY_PINS: usize = 16;
+pub(crate) const PATTERN_CHUNK: usize = 48; // table bytes per PATTERN_DATA, so they fit in the board's frame buffer
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub pins: Vec<u8>,   // hw_ids
+    pub steps: Vec<u16>, // bit i of each step is the level of pins[i]
+    pub period_us: u32,
+    pub repeat: u16, // times through the table; 0 keeps going until it's stopped
+}
+
+impl Pattern {
+    pub fn level(&self, step: usize, column: usize) -> bool {
+        self.steps[step] & (1 << column) != 0
+    }
+
+    pub(crate) fn packed(&self) -> Vec<u8> {
+        let mut packed = vec![0; self.packed_len()];
+        for step in 0..self.steps.len() {
+            for column in 0..self.pins.len() {
+                let bit = step * self.pins.len() + column;
+                if self.level(step, column) {
+                    packed[bit / 8] |= 1 << (bit % 8);
+                }
+            }
+        }
+        packed
+    }
+
+    pub(crate) fn packed_len(&self) -> usize {
+        (self.pins.len() * self.steps.len()).div_ceil(8)
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        // PERIOD_US (u32 LE), REPEAT (u16 LE), STEPS (u16 LE), PIN_COUNT, PINS
+        let mut payload = self.period_us.to_le_bytes().to_vec();
+        payload.extend_from_slice(&self.repeat.to_le_bytes());
+        payload.extend_from_slice(&(self.steps.len() as u16).to_le_bytes());
+        payload.push(self.pins.len() as u8);
+        payload.extend_from_slice(&self.pins);
+        payload
+    }
+}
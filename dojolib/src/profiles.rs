@@ -0,0 +1,328 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// known boards.
+// dojocore only tells us a board's name and its pins. a profile fills in what it can't say: where each pin sits on the
+// board's headers, what voltage its logic runs at, and how to turn an ADC reading into volts. look one up with
+// by_description() (pass it Board::get_name()) or by_usb_id() (the VID and PID of the board's serial port), or
+// identify() with both; Board::profile() already does that.
+//
+// positions are in millimeters from the top left corner of the board, turned so the USB connector is on the left.
+
+pub const PIN_PITCH: f32 = 2.54; // every header here is on 0.1" centers
+
+pub struct Profile {
+    pub name: &'static str,
+    pub description: &'static str, // what dojocore calls the board, minus " running CircuitDojo"
+    pub usb_ids: &'static [(u16, u16)], // VID, PID of the board's own USB interface
+    pub logic_voltage: f32,
+    pub adc_bits: u8,
+    pub adc_reference: f32, // volts at full scale
+    pub size: (f32, f32),   // width, height
+    pub headers: &'static [Header],
+}
+
+pub struct Header {
+    pub x: f32, // where the first pin is
+    pub y: f32,
+    pub vertical: bool, // pins run down from the first one instead of across
+    // silkscreen labels, first pin first. "D5" and "A0" are the pins dojocore calls "Digital 5" and "Analog 0";
+    // anything else (GND, 5V, ...) matches a dojocore identifier exactly, if at all
+    pub pins: &'static [&'static str],
+}
+
+impl Profile {
+    pub fn adc_max(&self) -> u16 {
+        // the biggest reading the ADC gives, at adc_reference volts
+        (1 << self.adc_bits) - 1
+    }
+
+    pub fn volts(&self, reading: u16) -> f32 {
+        reading as f32 * self.adc_reference / self.adc_max() as f32
+    }
+
+    pub fn position(&self, ident: &str) -> Option<(f32, f32)> {
+        // where the pin with this dojocore identifier sits on the board
+        let label = silkscreen(ident);
+        self.headers.iter().find_map(|header| {
+            let i = header.pins.iter().position(|pin| *pin == label)? as f32;
+            Some(if header.vertical {
+                (header.x, header.y + i * PIN_PITCH)
+            } else {
+                (header.x + i * PIN_PITCH, header.y)
+            })
+        })
+    }
+}
+
+pub fn silkscreen(ident: &str) -> String {
+    // the label printed next to the pin with this dojocore identifier
+    if let Some(number) = ident.strip_prefix("Digital ") {
+        format!("D{}", number)
+    } else if let Some(number) = ident.strip_prefix("Analog ") {
+        format!("A{}", number)
+    } else {
+        ident.to_string()
+    }
+}
+
+pub fn by_description(description: &str) -> Option<&'static Profile> {
+    PROFILES
+        .iter()
+        .copied()
+        .find(|profile| description.starts_with(profile.description))
+}
+
+pub fn by_usb_id(vid: u16, pid: u16) -> Option<&'static Profile> {
+    PROFILES
+        .iter()
+        .copied()
+        .find(|profile| profile.usb_ids.contains(&(vid, pid)))
+}
+
+pub fn identify(description: &str, usb_id: Option<(u16, u16)>) -> Option<&'static Profile> {
+    // the name is more specific (an UNO clone can have anybody's USB chip), but dojocore calls any board it doesn't
+    // have a pin table for an UNO, so a USB id that says otherwise wins over that name
+    let by_usb = usb_id.and_then(|(vid, pid)| by_usb_id(vid, pid));
+    match by_description(description) {
+        Some(profile) if profile.description != UNO_R3.description => Some(profile),
+        by_name => by_usb.or(by_name),
+    }
+}
+
+pub static PROFILES: &[&Profile] = &[&UNO_R3, &NANO, &MEGA_2560, &LEONARDO, &PRO_MICRO, &PICO];
+
+// the R3 shield layout, shared by the UNO and the Leonardo
+const R3_HEADERS: &[Header] = &[
+    Header {
+        x: 17.5,
+        y: 2.5,
+        vertical: false,
+        pins: &[
+            "SCL", "SDA", "AREF", "GND", "D13", "D12", "D11", "D10", "D9", "D8",
+        ],
+    },
+    Header {
+        x: 44.5,
+        y: 2.5,
+        vertical: false,
+        pins: &["D7", "D6", "D5", "D4", "D3", "D2", "D1", "D0"],
+    },
+    Header {
+        x: 27.9,
+        y: 50.8,
+        vertical: false,
+        pins: &["NC", "IOREF", "RESET", "3V3", "5V", "GND", "GND", "VIN"],
+    },
+    Header {
+        x: 50.8,
+        y: 50.8,
+        vertical: false,
+        pins: &["A0", "A1", "A2", "A3", "A4", "A5"],
+    },
+];
+
+pub const UNO_R3: Profile = Profile {
+    name: "Arduino UNO R3",
+    description: "Arduino UNO R3",
+    usb_ids: &[
+        (0x2341, 0x0043),
+        (0x2341, 0x0001),
+        (0x2A03, 0x0043),
+        (0x2341, 0x0243),
+    ],
+    logic_voltage: 5.0,
+    adc_bits: 10,
+    adc_reference: 5.0,
+    size: (68.6, 53.3),
+    headers: R3_HEADERS,
+};
+
+pub const NANO: Profile = Profile {
+    name: "Arduino Nano",
+    description: "Arduino Nano",
+    usb_ids: &[], // an FTDI or CH340 chip that half the clones on the market use too, so it can't say which board it is
+    logic_voltage: 5.0,
+    adc_bits: 10,
+    adc_reference: 5.0,
+    size: (43.2, 17.8),
+    headers: &[
+        Header {
+            x: 3.8,
+            y: 1.3,
+            vertical: false,
+            pins: &[
+                "D13", "3V3", "AREF", "A0", "A1", "A2", "A3", "A4", "A5", "A6", "A7", "5V", "RST",
+                "GND", "VIN",
+            ],
+        },
+        Header {
+            x: 3.8,
+            y: 16.5,
+            vertical: false,
+            pins: &[
+                "D12", "D11", "D10", "D9", "D8", "D7", "D6", "D5", "D4", "D3", "D2", "GND", "RST",
+                "D0", "D1",
+            ],
+        },
+    ],
+};
+
+pub const MEGA_2560: Profile = Profile {
+    name: "Arduino Mega 2560",
+    description: "Arduino Mega 2560",
+    usb_ids: &[
+        (0x2341, 0x0010),
+        (0x2341, 0x0042),
+        (0x2A03, 0x0010),
+        (0x2A03, 0x0042),
+        (0x2341, 0x0242),
+    ],
+    logic_voltage: 5.0,
+    adc_bits: 10,
+    adc_reference: 5.0,
+    size: (101.6, 53.3),
+    headers: &[
+        Header {
+            x: 17.5,
+            y: 2.5,
+            vertical: false,
+            pins: &[
+                "SCL", "SDA", "AREF", "GND", "D13", "D12", "D11", "D10", "D9", "D8",
+            ],
+        },
+        Header {
+            x: 44.5,
+            y: 2.5,
+            vertical: false,
+            pins: &["D7", "D6", "D5", "D4", "D3", "D2", "D1", "D0"],
+        },
+        Header {
+            x: 66.0,
+            y: 2.5,
+            vertical: false,
+            pins: &["D14", "D15", "D16", "D17", "D18", "D19", "D20", "D21"],
+        },
+        Header {
+            x: 27.9,
+            y: 50.8,
+            vertical: false,
+            pins: &["NC", "IOREF", "RESET", "3V3", "5V", "GND", "GND", "VIN"],
+        },
+        Header {
+            x: 50.8,
+            y: 50.8,
+            vertical: false,
+            pins: &["A0", "A1", "A2", "A3", "A4", "A5", "A6", "A7"],
+        },
+        Header {
+            x: 73.7,
+            y: 50.8,
+            vertical: false,
+            pins: &["A8", "A9", "A10", "A11", "A12", "A13", "A14", "A15"],
+        },
+        // the double row along the right edge: even pins in one column, odd in the other
+        Header {
+            x: 93.9,
+            y: 5.1,
+            vertical: true,
+            pins: &[
+                "5V", "D22", "D24", "D26", "D28", "D30", "D32", "D34", "D36", "D38", "D40", "D42",
+                "D44", "D46", "D48", "D50", "D52", "GND",
+            ],
+        },
+        Header {
+            x: 96.4,
+            y: 5.1,
+            vertical: true,
+            pins: &[
+                "5V", "D23", "D25", "D27", "D29", "D31", "D33", "D35", "D37", "D39", "D41", "D43",
+                "D45", "D47", "D49", "D51", "D53", "GND",
+            ],
+        },
+    ],
+};
+
+pub const LEONARDO: Profile = Profile {
+    name: "Arduino Leonardo",
+    description: "Arduino Leonardo",
+    usb_ids: &[
+        (0x2341, 0x0036),
+        (0x2341, 0x8036),
+        (0x2A03, 0x0036),
+        (0x2A03, 0x8036),
+    ],
+    logic_voltage: 5.0,
+    adc_bits: 10,
+    adc_reference: 5.0,
+    size: (68.6, 53.3),
+    headers: R3_HEADERS,
+};
+
+pub const PRO_MICRO: Profile = Profile {
+    name: "SparkFun Pro Micro (5V)",
+    description: "SparkFun Pro Micro",
+    usb_ids: &[(0x1B4F, 0x9205), (0x1B4F, 0x9206)],
+    logic_voltage: 5.0,
+    adc_bits: 10,
+    adc_reference: 5.0,
+    size: (33.0, 17.8),
+    headers: &[
+        Header {
+            x: 2.5,
+            y: 1.3,
+            vertical: false,
+            pins: &[
+                "D1", "D0", "GND", "GND", "D2", "D3", "D4", "D5", "D6", "D7", "D8", "D9",
+            ],
+        },
+        Header {
+            x: 2.5,
+            y: 16.5,
+            vertical: false,
+            pins: &[
+                "RAW", "GND", "RST", "VCC", "A3", "A2", "A1", "A0", "D15", "D14", "D16", "D10",
+            ],
+        },
+    ],
+};
+
+pub const PICO: Profile = Profile {
+    name: "Raspberry Pi Pico",
+    description: "Raspberry Pi Pico",
+    usb_ids: &[(0x2E8A, 0x000A), (0x2E8A, 0x00C0)],
+    logic_voltage: 3.3,
+    adc_bits: 10, // the ADC has 12, but analogRead gives 10 unless you ask for more, and dojocore doesn't
+    adc_reference: 3.3,
+    size: (51.0, 21.0),
+    headers: &[
+        // pin 1 is next to the USB connector. the ADC inputs (GP26-28) go by A0-A2
+        Header {
+            x: 1.6,
+            y: 1.6,
+            vertical: false,
+            pins: &[
+                "GP0", "GP1", "GND", "GP2", "GP3", "GP4", "GP5", "GND", "GP6", "GP7", "GP8", "GP9",
+                "GND", "GP10", "GP11", "GP12", "GP13", "GND", "GP14", "GP15",
+            ],
+        },
+        Header {
+            x: 1.6,
+            y: 19.4,
+            vertical: false,
+            pins: &[
+                "VBUS", "VSYS", "GND", "3V3_EN", "3V3", "ADC_VREF", "A2", "AGND", "A1", "A0",
+                "RUN", "GP22", "GND", "GP21", "GP20", "GP19", "GP18", "GND", "GP17", "GP16",
+            ],
+        },
+    ],
+};
@@ -0,0 +1,513 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// pin history.
+// Board only keeps the latest status of each pin; a Recorder keeps every change, with the host time it was
+// seen (and the board's own time, when the board sends one), in a fixed-size ring buffer. once the buffer's
+// full the oldest samples fall off the end.
+//
+// recordings can be written out as CSV or JSON Lines for handing in or loading into a spreadsheet, or as a
+// VCD (IEEE 1364 value change dump) for opening in GTKWave or PulseView next to simulation output.
+
+use crate::board::{PinData, PinStatus};
+use crate::{DOJOLIB_VERSION, Result};
+use std::collections::VecDeque;
+use std::collections::vec_deque::Iter;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Sample {
+    pub host_time: Duration,     // since the recording started
+    pub board_time: Option<u64>, // microseconds on the board's clock, if it reported one
+    pub pin: u8,                 // hw_id
+    pub status: PinStatus,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedPin {
+    pub hw_id: u8,
+    pub ident: String,
+    pub label: Option<String>,
+}
+
+impl RecordedPin {
+    pub fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.ident)
+    }
+}
+
+pub struct Recorder {
+    samples: VecDeque<Sample>,
+    capacity: usize,
+    recorded: u64, // every sample ever recorded, including the ones that have fallen off the front
+    pins: Vec<RecordedPin>,
+    started: Instant,
+    started_wall: SystemTime,
+}
+
+impl Recorder {
+    pub fn new<'a>(capacity: usize, pins: impl IntoIterator<Item = &'a PinData>) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            recorded: 0,
+            pins: pins
+                .into_iter()
+                .map(|pin| RecordedPin {
+                    hw_id: pin.hw_id,
+                    ident: pin.ident.clone(),
+                    label: pin.label.clone(),
+                })
+                .collect(),
+            started: Instant::now(),
+            started_wall: SystemTime::now(),
+        }
+    }
+
+    pub fn record(&mut self, at: Instant, board_time: Option<u64>, pin: u8, status: PinStatus) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            host_time: at.saturating_duration_since(self.started),
+            board_time,
+            pin,
+            status,
+        });
+        self.recorded += 1;
+    }
+
+    pub fn samples(&self) -> Iter<'_, Sample> {
+        self.samples.iter()
+    }
+
+    pub fn total(&self) -> u64 {
+        // how many samples have ever been recorded. unlike len, this never goes down, so it works as a bookmark
+        self.recorded
+    }
+
+    pub fn since(&self, total: u64) -> std::iter::Skip<Iter<'_, Sample>> {
+        // the samples recorded after total() returned this, or as many of them as are still around
+        let dropped = self.recorded - self.samples.len() as u64;
+        self.samples
+            .iter()
+            .skip(total.saturating_sub(dropped) as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn pins(&self) -> &[RecordedPin] {
+        &self.pins
+    }
+
+    pub fn pin(&self, hw_id: u8) -> Option<&RecordedPin> {
+        self.pins.iter().find(|pin| pin.hw_id == hw_id)
+    }
+
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    pub fn started_wall(&self) -> SystemTime {
+        // wall-clock time the recording started, for putting in file headers and the like
+        self.started_wall
+    }
+
+    pub fn write_csv(&self, mut out: impl Write) -> Result<()> {
+        writeln!(out, "host_time_s,board_time_us,pin,name,kind,value")?;
+        for sample in &self.samples {
+            let (kind, value) = status_fields(sample.status);
+            writeln!(
+                out,
+                "{:.6},{},{},{},{},{}",
+                sample.host_time.as_secs_f64(),
+                sample
+                    .board_time
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+                sample.pin,
+                csv_escape(self.pin_name(sample.pin)),
+                kind,
+                value.map(|value| value.to_string()).unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_jsonl(&self, mut out: impl Write) -> Result<()> {
+        for sample in &self.samples {
+            let (kind, value) = status_fields(sample.status);
+            let line = serde_json::json!({
+                "host_time_s": sample.host_time.as_secs_f64(),
+                "board_time_us": sample.board_time,
+                "pin": sample.pin,
+                "name": self.pin_name(sample.pin),
+                "kind": kind,
+                "value": value,
+            });
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_vcd(&self, mut out: impl Write) -> Result<()> {
+        // one wire per pin that shows up in the recording, named after its identifier, on a 1us timescale.
+        // digital pins are 1-bit wires. pins that ever read or wrote an analog value are 10-bit (analog in) or
+        // 8-bit (pwm) vectors instead, with digital levels on them dumped as all zeroes or all ones.
+        let mut samples: Vec<&Sample> = self.samples.iter().collect();
+        // outputs are stamped when the command is sent and inputs when the worker reads them, so the buffer can be a
+        // hair out of order. VCD wants time to only go forwards.
+        samples.sort_by_key(|sample| sample.host_time);
+        let mut wires: Vec<(u8, u32)> = vec![]; // hw_id, width
+        for sample in &samples {
+            let width = match sample.status {
+                PinStatus::AnalogInputting(_) => 10,
+                PinStatus::AnalogOutputting(_) => 8,
+                _ => 1,
+            };
+            match wires.iter_mut().find(|(pin, _)| *pin == sample.pin) {
+                Some((_, existing)) => *existing = (*existing).max(width),
+                None => wires.push((sample.pin, width)),
+            }
+        }
+        wires.sort();
+        writeln!(out, "$date {} $end", utc_date(self.started_wall))?;
+        writeln!(
+            out,
+            "$version CircuitDojo dojolib v{} $end",
+            DOJOLIB_VERSION
+        )?;
+        writeln!(out, "$timescale 1us $end")?;
+        writeln!(out, "$scope module circuitdojo $end")?;
+        for (i, (pin, width)) in wires.iter().enumerate() {
+            let name = self
+                .pin(*pin)
+                .map(|pin| pin.ident.replace(char::is_whitespace, "_"))
+                .unwrap_or_else(|| format!("pin_{}", pin));
+            writeln!(out, "$var wire {} {} {} $end", width, vcd_id(i), name)?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+        writeln!(out, "$dumpvars")?;
+        for (i, (_, width)) in wires.iter().enumerate() {
+            if *width == 1 {
+                writeln!(out, "x{}", vcd_id(i))?;
+            } else {
+                writeln!(out, "bx {}", vcd_id(i))?;
+            }
+        }
+        writeln!(out, "$end")?;
+        let mut last_time = None;
+        for sample in samples {
+            let (i, (_, width)) = wires
+                .iter()
+                .enumerate()
+                .find(|(_, (pin, _))| *pin == sample.pin)
+                .unwrap(); // every sampled pin got a wire above
+            let time = sample.host_time.as_micros();
+            if last_time != Some(time) {
+                writeln!(out, "#{}", time)?;
+                last_time = Some(time);
+            }
+            let value = match sample.status {
+                PinStatus::NoStatus => None,
+                PinStatus::DigitalOutputting(level)
+                | PinStatus::DigitalInputting(level)
+                | PinStatus::DigitalPullupInputting(level) => {
+                    Some(if level { (1u32 << width) - 1 } else { 0 })
+                }
+                PinStatus::AnalogOutputting(value) | PinStatus::AnalogInputting(value) => {
+                    Some(value as u32)
+                }
+            };
+            match (value, *width) {
+                (None, 1) => writeln!(out, "x{}", vcd_id(i))?,
+                (None, _) => writeln!(out, "bx {}", vcd_id(i))?,
+                (Some(value), 1) => writeln!(out, "{}{}", value, vcd_id(i))?,
+                (Some(value), _) => writeln!(out, "b{:b} {}", value, vcd_id(i))?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        // write to a file, picking the format from the extension: .jsonl or .json for JSON Lines, .vcd for a value
+        // change dump, anything else is CSV
+        let path = path.as_ref();
+        let out = BufWriter::new(std::fs::File::create(path)?);
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl" | "json") => self.write_jsonl(out),
+            Some("vcd") => self.write_vcd(out),
+            _ => self.write_csv(out),
+        }
+    }
+
+    fn pin_name(&self, hw_id: u8) -> &str {
+        self.pin(hw_id).map(|pin| pin.name()).unwrap_or("")
+    }
+}
+
+fn status_fields(status: PinStatus) -> (&'static str, Option<u16>) {
+    // a PinStatus as a (kind, value) pair for the exporters. digital levels are 0 or 1.
+    match status {
+        PinStatus::NoStatus => ("none", None),
+        PinStatus::DigitalOutputting(level) => ("digital_out", Some(level as u16)),
+        PinStatus::DigitalInputting(level) => ("digital_in", Some(level as u16)),
+        PinStatus::DigitalPullupInputting(level) => ("pullup_in", Some(level as u16)),
+        PinStatus::AnalogOutputting(duty) => ("pwm_out", Some(duty)),
+        PinStatus::AnalogInputting(value) => ("analog_in", Some(value)),
+    }
+}
+
+fn utc_date(time: SystemTime) -> String {
+    // "YYYY-MM-DD HH:MM:SS UTC", without pulling in a whole date library for one header line
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn vcd_id(index: usize) -> String {
+    // VCD identifiers are short strings of printable ascii ('!' through '~')
+    let mut index = index;
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{PinMode, PinType};
+
+    fn pin(hw_id: u8, ident: &str, label: Option<&str>) -> PinData {
+        PinData {
+            tp: PinType::Digital,
+            pwm: false,
+            mode: PinMode::Unset,
+            hw_id,
+            ident: ident.to_string(),
+            status: PinStatus::NoStatus,
+            label: label.map(str::to_string),
+            locked: false,
+        }
+    }
+
+    fn recording() -> Recorder {
+        // a digital output, a pwm pin that ends up driven digitally, and an analog input, started on a leap day
+        let pins = [
+            pin(0, "Digital 3", Some("LED, red")),
+            pin(1, "Digital 5", Some("say \"hi\"")),
+            pin(2, "Analog 0", None),
+        ];
+        let mut recorder = Recorder::new(16, &pins);
+        recorder.started_wall = SystemTime::UNIX_EPOCH + Duration::from_secs(1709251199);
+        let t0 = recorder.started;
+        let ms = |tenths: u64| t0 + Duration::from_micros(tenths * 100);
+        recorder.record(ms(5), None, 0, PinStatus::DigitalOutputting(true));
+        recorder.record(ms(10), Some(1234), 2, PinStatus::AnalogInputting(512));
+        recorder.record(ms(10), None, 1, PinStatus::AnalogOutputting(128));
+        recorder.record(ms(25), Some(2734), 0, PinStatus::DigitalOutputting(false));
+        recorder.record(ms(30), None, 1, PinStatus::DigitalOutputting(true));
+        recorder
+    }
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> String {
+        let mut out = vec![];
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_quotes_awkward_names() {
+        let csv = written(|out| recording().write_csv(out));
+        assert_eq!(
+            csv,
+            "host_time_s,board_time_us,pin,name,kind,value\n\
+             0.000500,,0,\"LED, red\",digital_out,1\n\
+             0.001000,1234,2,Analog 0,analog_in,512\n\
+             0.001000,,1,\"say \"\"hi\"\"\",pwm_out,128\n\
+             0.002500,2734,0,\"LED, red\",digital_out,0\n\
+             0.003000,,1,\"say \"\"hi\"\"\",digital_out,1\n"
+        );
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn jsonl_has_a_line_per_sample() {
+        let jsonl = written(|out| recording().write_jsonl(out));
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[1],
+            serde_json::json!({
+                "host_time_s": 0.001,
+                "board_time_us": 1234,
+                "pin": 2,
+                "name": "Analog 0",
+                "kind": "analog_in",
+                "value": 512,
+            })
+        );
+        assert_eq!(lines[0]["board_time_us"], serde_json::Value::Null);
+        assert_eq!(lines[2]["name"], "say \"hi\"");
+    }
+
+    #[test]
+    fn vcd_gives_each_kind_of_pin_its_width() {
+        let vcd = written(|out| recording().write_vcd(out));
+        assert_eq!(
+            vcd,
+            format!(
+                "$date 2024-02-29 23:59:59 UTC $end\n\
+                 $version CircuitDojo dojolib v{} $end\n\
+                 $timescale 1us $end\n\
+                 $scope module circuitdojo $end\n\
+                 $var wire 1 ! Digital_3 $end\n\
+                 $var wire 8 \" Digital_5 $end\n\
+                 $var wire 10 # Analog_0 $end\n\
+                 $upscope $end\n\
+                 $enddefinitions $end\n\
+                 $dumpvars\n\
+                 x!\n\
+                 bx \"\n\
+                 bx #\n\
+                 $end\n\
+                 #500\n\
+                 1!\n\
+                 #1000\n\
+                 b1000000000 #\n\
+                 b10000000 \"\n\
+                 #2500\n\
+                 0!\n\
+                 #3000\n\
+                 b11111111 \"\n",
+                DOJOLIB_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn vcd_ids_stay_unique_past_94_signals() {
+        assert_eq!(vcd_id(0), "!");
+        assert_eq!(vcd_id(93), "~");
+        assert_eq!(vcd_id(94), "!!");
+        assert_eq!(vcd_id(95), "\"!");
+        assert_eq!(vcd_id(94 + 94 * 94 - 1), "~~");
+        assert_eq!(vcd_id(94 + 94 * 94), "!!!");
+        let ids: std::collections::HashSet<String> = (0..20000).map(vcd_id).collect();
+        assert_eq!(ids.len(), 20000);
+        assert!(
+            ids.iter()
+                .all(|id| id.bytes().all(|byte| (b'!'..=b'~').contains(&byte)))
+        );
+
+        // and in a real dump: the 95th pin gets the first two-character id
+        let pins: Vec<PinData> = (0..100)
+            .map(|i| pin(i, &format!("Digital {}", i), None))
+            .collect();
+        let mut recorder = Recorder::new(100, &pins);
+        for i in 0..100 {
+            recorder.record(recorder.started, None, i, PinStatus::DigitalInputting(true));
+        }
+        let vcd = written(|out| recorder.write_vcd(out));
+        assert!(vcd.contains("$var wire 1 ~ Digital_93 $end\n$var wire 1 !! Digital_94 $end\n"));
+        assert!(vcd.contains("\n1!!\n"));
+    }
+
+    #[test]
+    fn since_survives_the_ring_wrapping() {
+        let pins = [pin(0, "Digital 3", None)];
+        let mut recorder = Recorder::new(3, &pins);
+        let bookmark = recorder.total();
+        for i in 0..5u64 {
+            let at = recorder.started + Duration::from_millis(i);
+            recorder.record(at, Some(i), 0, PinStatus::DigitalInputting(i % 2 == 0));
+        }
+        let times = |from: u64| -> Vec<u64> {
+            recorder
+                .since(from)
+                .map(|sample| sample.board_time.unwrap())
+                .collect()
+        };
+        assert_eq!(recorder.total(), 5);
+        assert_eq!(recorder.len(), 3);
+        // the first two fell off, so a bookmark from before them gets what's left
+        assert_eq!(times(bookmark), vec![2, 3, 4]);
+        assert_eq!(times(3), vec![3, 4]);
+        assert_eq!(times(5), Vec::<u64>::new());
+        recorder.clear();
+        assert_eq!(recorder.total(), 5);
+    }
+
+    #[test]
+    fn utc_date_handles_leap_days() {
+        let at = |secs| utc_date(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(at(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(at(1709251199), "2024-02-29 23:59:59 UTC");
+        assert_eq!(at(1709251200), "2024-03-01 00:00:00 UTC");
+    }
+}
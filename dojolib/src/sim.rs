@@ -0,0 +1,1245 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// a software dojocore.
+// SimBoard is a rust port of the state machine in dojocore.ino, running on its own thread behind an in-memory
+// pipe. Board (or a raw Connection) can't tell the difference, so you can poke at the whole stack without an
+// Arduino plugged in: tests, CI, demos, whatever.
+//
+// this should stay byte-for-byte compatible with the real firmware. if you change the protocol in one place,
+// change it in the other!
+//
+// you can also wire the virtual board up to itself (see Wire), so outputs drive inputs just like jumper wires
+// on a real breadboard would.
+
+use crate::bus::MAX_BUS_PINS;
+use crate::capture::MAX_CAPTURE_PINS;
+use crate::opcodes::frame::{self, Deframer, Frame};
+use crate::opcodes::{COMPACT_PINS, capability, miso, mosi};
+use crate::pattern::MAX_PLAY_PINS;
+use crate::transport::{MemoryTransport, Transport, pipe};
+use crate::trigger::MAX_PATTERN_PINS;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+const ADC_MAX: u16 = 1023;
+const DIG_NONE: u16 = 1024;
+const DIG_LOW: u16 = 1025;
+const DIG_HIGH: u16 = 1026;
+
+const MODE_NONE: u8 = 0;
+const MODE_INPUT: u8 = 1;
+const MODE_OUTPUT: u8 = 2;
+
+const CAPTURE_CHUNK: usize = 48; // packed sample bytes per CAPTURE_DATA
+
+#[derive(Clone)]
+pub struct SimPin {
+    // one entry in the board's pin table, same as pindef in the firmware
+    pub physical_pin: u8,
+    pub is_analog: bool,
+    pub has_pullup: bool,
+    pub has_pwm: bool,
+    pub identifier: String,
+}
+
+impl SimPin {
+    pub fn digital(physical_pin: u8) -> Self {
+        Self {
+            physical_pin,
+            is_analog: false,
+            has_pullup: true,
+            has_pwm: false,
+            identifier: format!("Digital {}", physical_pin),
+        }
+    }
+
+    pub fn analog(physical_pin: u8, channel: u8) -> Self {
+        Self {
+            physical_pin,
+            is_analog: true,
+            has_pullup: false,
+            has_pwm: false,
+            identifier: format!("Analog {}", channel),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Wire {
+    // something connected between physical pins on the virtual board.
+    // pins are addressed by their arduino pin number (SimPin::physical_pin), not their index in the pin table.
+    Tie(u8, u8),      // a plain jumper: both pins are on the same net
+    Inverter(u8, u8), // a NOT gate from the first pin (gate input) to the second pin (gate output)
+    PullUp(u8),       // a resistor to 5v: the pin reads high unless something drives it low
+    Analog(u8, u16), // a fixed voltage source (say, a potentiometer), given as the 10-bit reading it produces
+}
+
+pub struct SimBoard {
+    name: String,
+    pins: Vec<SimPin>,
+    wires: Vec<Wire>,
+    states: Vec<u16>, // 0-1023 = analog, 1024=none, 1025=digital low, 1026=digital high
+    modes: Vec<u8>,   // 0=none, 1=input, 2=output
+    pullups: Vec<bool>, // internal pullup turned on (only meaningful for inputs)
+    drive: Vec<u16>, // what each pin puts out when it's an output, on the adc's 0-1023 scale (pwm shows up as its average)
+    subsc_wavelength: u16,
+    last_update: Instant,
+    version: u8,        // protocol version for this session; back to 1 at every handshake
+    timestamps: bool,   // also back off at every handshake
+    booted: Instant,    // for micros()
+    capture_bytes: u16, // the capture buffer's size, same as CAPTURE_BYTES in the firmware
+    pattern: Vec<u8>,   // the pattern table, PATTERN_BYTES long
+    loaded: usize,      // how much of the table has come in, in order from the start
+    playing: Option<SimPlayback>,
+}
+
+struct SimPlayback {
+    seq: u8, // of the PLAY_PATTERN, for its PATTERN_DONE
+    pins: Vec<usize>,
+    steps: usize,
+    period: Duration,
+    repeat: u16, // 0 is forever
+    round: u16,
+    step: usize, // the next one to play
+    next: Instant,
+    shown: Option<usize>, // the last one played
+}
+
+impl SimBoard {
+    pub fn new(name: impl Into<String>, pins: Vec<SimPin>) -> Self {
+        let count = pins.len();
+        Self {
+            name: name.into(),
+            pins,
+            wires: vec![],
+            states: vec![DIG_NONE; count],
+            modes: vec![MODE_NONE; count],
+            pullups: vec![false; count],
+            drive: vec![0; count],
+            subsc_wavelength: 0,
+            last_update: Instant::now(),
+            version: 1,
+            timestamps: false,
+            booted: Instant::now(),
+            capture_bytes: 1024,
+            pattern: vec![0; 256],
+            loaded: 0,
+            playing: None,
+        }
+    }
+
+    pub fn uno() -> Self {
+        // the same pin table dojocore ships with
+        Self::new(
+            "Arduino UNO R3 running CircuitDojo (simulated)",
+            (3..=13)
+                .map(|pin| SimPin {
+                    has_pwm: [3, 5, 6, 9, 10, 11].contains(&pin),
+                    ..SimPin::digital(pin)
+                })
+                .chain((0..6).map(|channel| SimPin::analog(14 + channel, channel))) // A0 is pin 14 on the UNO
+                .collect(),
+        )
+    }
+
+    pub fn mega() -> Self {
+        // dojocore's Mega 2560 pin table. it's past the 64 pins the compact encoding can address.
+        // it has four times the UNO's RAM, so captures get four times the room
+        let board = Self::new(
+            "Arduino Mega 2560 running CircuitDojo (simulated)",
+            (2..=53)
+                .map(|pin| SimPin {
+                    has_pwm: (2..=13).contains(&pin) || (44..=46).contains(&pin),
+                    ..SimPin::digital(pin)
+                })
+                .chain((0..16).map(|channel| SimPin::analog(54 + channel, channel))) // A0 is pin 54 on the Mega
+                .collect(),
+        );
+        Self {
+            capture_bytes: 4096,
+            pattern: vec![0; 1024],
+            ..board
+        }
+    }
+
+    pub fn wire(mut self, wire: Wire) -> Self {
+        // add a wire to the board. all wiring has to be done before spawn().
+        self.wires.push(wire);
+        self
+    }
+
+    pub fn spawn(self) -> MemoryTransport {
+        // start the board on its own thread and hand back the host's end of the wire.
+        // the thread exits when the host end is dropped.
+        let (host, device) = pipe();
+        std::thread::spawn(move || self.run(device));
+        host
+    }
+
+    fn run(mut self, mut port: MemoryTransport) {
+        // the firmware's loop(): wait for a handshake, then serve until something goes wrong
+        if port.set_timeout(Duration::from_millis(1)).is_err() {
+            return;
+        }
+        loop {
+            match block_read(&mut port) {
+                Ok(mosi::PLEASE_ESTABLISH) => {
+                    if port.write_all(&[miso::ACK]).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => continue,
+                Err(_) => return,
+            }
+            if self.serve(&mut port).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn read_all(&self) -> Vec<u16> {
+        // work out what every pin in the pin table would read, as a 10-bit ADC value (digital pins read high above 511).
+        // pins joined by Ties share a net. a net is driven by the outputs on it, by any inverters feeding it, and by
+        // any Analog sources on it; if nothing drives it, it reads high when pulled up and low otherwise (real
+        // floating pins are less polite). when drivers disagree the lowest one wins.
+        let mut nets = Nets::default();
+        for pin in &self.pins {
+            nets.find(pin.physical_pin);
+        }
+        for wire in &self.wires {
+            match *wire {
+                Wire::Tie(a, b) => nets.join(a, b),
+                Wire::Inverter(a, b) => {
+                    nets.find(a);
+                    nets.find(b);
+                }
+                Wire::PullUp(pin) | Wire::Analog(pin, _) => {
+                    nets.find(pin);
+                }
+            }
+        }
+        let mut levels: HashMap<u8, u16> = HashMap::new();
+        // inverters can feed each other, so settle the whole thing a few times.
+        // anything that hasn't settled by then (a ring oscillator, say) just reads whatever it landed on.
+        for _ in 0..8 {
+            let mut drivers: HashMap<u8, Vec<u16>> = HashMap::new();
+            for (i, pin) in self.pins.iter().enumerate() {
+                if self.modes[i] == MODE_OUTPUT {
+                    let net = nets.find(pin.physical_pin);
+                    drivers.entry(net).or_default().push(self.drive[i]);
+                }
+            }
+            for wire in &self.wires {
+                match *wire {
+                    Wire::Inverter(input, output) => {
+                        let input = nets.find(input);
+                        let output = nets.find(output);
+                        let high = levels.get(&input).copied().unwrap_or(0) > ADC_MAX / 2;
+                        drivers
+                            .entry(output)
+                            .or_default()
+                            .push(if high { 0 } else { ADC_MAX });
+                    }
+                    Wire::Analog(pin, value) => {
+                        let net = nets.find(pin);
+                        drivers.entry(net).or_default().push(value.min(ADC_MAX));
+                    }
+                    _ => {}
+                }
+            }
+            let mut next = HashMap::new();
+            for net in nets.roots() {
+                let level = match drivers.get(&net) {
+                    Some(driven) => *driven.iter().min().unwrap(),
+                    None => {
+                        let pulled_up = self.wires.iter().any(|wire| match *wire {
+                            Wire::PullUp(pin) => nets.find(pin) == net,
+                            _ => false,
+                        }) || self.pins.iter().enumerate().any(|(i, pin)| {
+                            self.pullups[i]
+                                && self.modes[i] == MODE_INPUT
+                                && nets.find(pin.physical_pin) == net
+                        });
+                        if pulled_up { ADC_MAX } else { 0 }
+                    }
+                };
+                next.insert(net, level);
+            }
+            if next == levels {
+                break;
+            }
+            levels = next;
+        }
+        self.pins
+            .iter()
+            .map(|pin| levels[&nets.find(pin.physical_pin)])
+            .collect()
+    }
+
+    fn send(
+        &self,
+        port: &mut MemoryTransport,
+        seq: u8,
+        tp: u8,
+        payload: &[u8],
+    ) -> std::io::Result<()> {
+        // one message to the host, framed or not depending on what we negotiated
+        if self.version >= 2 {
+            return port.write_all(&frame::encode(seq, tp, payload));
+        }
+        match tp {
+            miso::DIGITAL_PIN_STATE if payload[0] < COMPACT_PINS => {
+                port.write_all(&[payload[0] | if payload[1] != 0 { 0x40 } else { 0 }])
+            }
+            miso::PIN_DESCRIPTION | miso::PIN_DESCRIPTION_WIDE | miso::BOARD_DESCRIPTION => {
+                port.write_all(&[tp])?;
+                port.write_all(payload)?;
+                port.write_all(&[0])
+            }
+            _ => {
+                port.write_all(&[tp])?;
+                port.write_all(payload)
+            }
+        }
+    }
+
+    fn micros(&self) -> u32 {
+        // wraps around after 71 minutes, like the real thing
+        self.booted.elapsed().as_micros() as u32
+    }
+
+    fn write_state(
+        &self,
+        port: &mut MemoryTransport,
+        pindex: usize,
+        value: u16,
+        micros: u32,
+    ) -> std::io::Result<()> {
+        let mut payload = if self.pins[pindex].is_analog {
+            let [lo, hi] = value.to_le_bytes();
+            vec![pindex as u8, lo, hi]
+        } else {
+            vec![pindex as u8, (value > ADC_MAX / 2) as u8]
+        };
+        let tp = match (self.pins[pindex].is_analog, self.timestamps) {
+            (true, false) => miso::ANALOG_PIN_STATE,
+            (false, false) => miso::DIGITAL_PIN_STATE,
+            (true, true) => miso::ANALOG_PIN_STATE_TIMED,
+            (false, true) => miso::DIGITAL_PIN_STATE_TIMED,
+        };
+        if self.timestamps {
+            payload.extend_from_slice(&micros.to_le_bytes());
+        }
+        self.send(port, 0, tp, &payload)
+    }
+
+    fn do_pin_updates(&mut self, port: &mut MemoryTransport, analog: bool) -> std::io::Result<()> {
+        // send every input that changed since we last said. with analog false, only the digital ones
+        let micros = self.micros();
+        for (i, value) in self.read_all().into_iter().enumerate() {
+            if self.modes[i] != MODE_INPUT || (self.pins[i].is_analog && !analog) {
+                continue;
+            }
+            let state = if self.pins[i].is_analog {
+                value
+            } else if value > ADC_MAX / 2 {
+                DIG_HIGH
+            } else {
+                DIG_LOW
+            };
+            if self.states[i] != state {
+                self.states[i] = state;
+                self.write_state(port, i, value, micros)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_mode(
+        &mut self,
+        port: &mut MemoryTransport,
+        seq: u8,
+        pindex: usize,
+        mode: u8,
+        pullup: bool,
+    ) -> std::io::Result<()> {
+        if pindex < self.pins.len() && (!pullup || self.pins[pindex].has_pullup) {
+            self.send(port, seq, miso::ACK, &[])?;
+            self.modes[pindex] = mode;
+            self.pullups[pindex] = pullup;
+        } else {
+            self.send(port, seq, miso::ERROR, &[])?;
+        }
+        Ok(())
+    }
+
+    fn digital_write(&mut self, pindex: usize, high: bool) {
+        if pindex < self.pins.len() {
+            self.drive[pindex] = if high { ADC_MAX } else { 0 };
+        }
+    }
+
+    fn play_pattern(&mut self, port: &mut MemoryTransport) -> std::io::Result<()> {
+        // put out the pattern's next step if it's time. falling behind just plays the late ones back to back
+        let Some(playing) = &mut self.playing else {
+            return Ok(());
+        };
+        if Instant::now() < playing.next {
+            return Ok(());
+        }
+        let bit = playing.step * playing.pins.len();
+        let levels: Vec<(usize, bool)> = playing
+            .pins
+            .iter()
+            .enumerate()
+            .map(|(column, pindex)| {
+                let bit = bit + column;
+                (*pindex, self.pattern[bit / 8] & (1 << (bit % 8)) != 0)
+            })
+            .collect();
+        playing.next += playing.period;
+        playing.shown = Some(playing.step);
+        playing.step += 1;
+        if playing.step == playing.steps {
+            playing.step = 0;
+            playing.round = playing.round.wrapping_add(1);
+        }
+        let finished = playing.repeat != 0 && playing.round == playing.repeat;
+        for (pindex, high) in levels {
+            // a pin switched away from being an output is left alone
+            if self.modes[pindex] == MODE_OUTPUT {
+                self.digital_write(pindex, high);
+            }
+        }
+        if finished {
+            self.stop_pattern(port)?;
+        }
+        Ok(())
+    }
+
+    fn stop_pattern(&mut self, port: &mut MemoryTransport) -> std::io::Result<()> {
+        if let Some(playing) = self.playing.take() {
+            let step = playing.shown.map_or(u16::MAX, |step| step as u16);
+            self.send(port, playing.seq, miso::PATTERN_DONE, &step.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn serve(&mut self, port: &mut MemoryTransport) -> std::io::Result<()> {
+        // past this point the handshake is complete! returns Ok when the board drops back to waiting for a handshake.
+        self.version = 1;
+        self.timestamps = false;
+        self.playing = None;
+        let mut deframer = Deframer::default();
+        loop {
+            let byte = match poll_read(port)? {
+                Some(byte) => byte,
+                None => {
+                    self.play_pattern(port)?;
+                    if self.subsc_wavelength != 0
+                        && self.last_update.elapsed().as_millis() > self.subsc_wavelength as u128
+                    {
+                        self.last_update = Instant::now();
+                        self.do_pin_updates(port, true)?;
+                    } else if self.timestamps && self.subsc_wavelength != 0 {
+                        // with timestamps on, digital inputs are watched all the time so edges get stamped when they
+                        // happen rather than at the next sample
+                        self.do_pin_updates(port, false)?;
+                    }
+                    continue;
+                }
+            };
+            if self.version >= 2 {
+                deframer.extend(&[byte]);
+                while let Some(Frame { seq, tp, payload }) = deframer.next_frame() {
+                    if !self.command(port, seq, tp, &payload)? {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            if byte & 0x80 == 0 {
+                // packed digital write
+                self.digital_write((byte & 0x3F) as usize, byte & 0x40 != 0);
+                continue;
+            }
+            let mut args = vec![0; arg_count(byte)];
+            for arg in args.iter_mut() {
+                *arg = block_read(port)?;
+            }
+            if !self.command(port, 0, byte, &args)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn capture(
+        &mut self,
+        port: &mut MemoryTransport,
+        seq: u8,
+        period: u32,
+        count: u16,
+        pin_count: usize,
+        rest: &[u8],
+    ) -> std::io::Result<()> {
+        // sample the pins round and round the buffer until the trigger fires, fill in the rest, then send it all.
+        // nothing else happens until it's done
+        let count = count as usize;
+        let bits = pin_count * count;
+        let pins = rest.get(..pin_count).unwrap_or_default();
+        let trigger = rest.get(pin_count..).and_then(SimTrigger::decode);
+        let digital = |pindex: &usize| self.pins.get(*pindex).is_some_and(|pin| !pin.is_analog);
+        let valid = (1..=MAX_CAPTURE_PINS).contains(&pin_count)
+            && count > 0
+            && bits <= self.capture_bytes as usize * 8
+            && pins.iter().all(|pindex| digital(&(*pindex as usize)))
+            && trigger.as_ref().is_some_and(|(trigger, _, pre)| {
+                *pre < count
+                    && match trigger {
+                        SimTrigger::Immediate => true,
+                        SimTrigger::Edge(pindex, _) | SimTrigger::Pulse(pindex, _, _) => {
+                            digital(pindex)
+                        }
+                        SimTrigger::Pattern(pattern) => {
+                            pattern.iter().all(|(pindex, _)| digital(pindex))
+                        }
+                        SimTrigger::Threshold(pindex, _, _) => {
+                            self.pins.get(*pindex).is_some_and(|pin| pin.is_analog)
+                        }
+                    }
+            });
+        let Some((trigger, timeout, pre)) = trigger.filter(|_| valid) else {
+            return self.send(port, seq, miso::ERROR, &[]);
+        };
+        let mut ring = vec![false; bits];
+        let began = Instant::now();
+        let mut next = began;
+        let mut first = 0;
+        let mut last;
+        let mut prev = match trigger {
+            SimTrigger::Pattern(_) => Some(false), // a pattern that's matched all along fires as soon as it's allowed to
+            _ => None,
+        };
+        let mut pulse_start = None;
+        let mut fired_at = None;
+        let mut triggered = false;
+        let mut sample = 0;
+        loop {
+            while let Some(wait) = next.checked_duration_since(Instant::now()) {
+                // a pattern keeps playing, so a capture can watch what it does
+                self.play_pattern(port)?;
+                let step = self
+                    .playing
+                    .as_ref()
+                    .map(|playing| playing.next.saturating_duration_since(Instant::now()));
+                std::thread::sleep(step.map_or(wait, |step| wait.min(step)));
+            }
+            self.play_pattern(port)?;
+            next += Duration::from_micros(period as u64);
+            last = self.micros();
+            if sample == 0 {
+                first = last;
+            }
+            let levels = self.read_all();
+            let slot = sample % count;
+            for (column, pindex) in pins.iter().enumerate() {
+                ring[slot * pin_count + column] = levels[*pindex as usize] > ADC_MAX / 2;
+            }
+            let high = |pindex: &usize| levels[*pindex] > ADC_MAX / 2;
+            let hit = match &trigger {
+                SimTrigger::Immediate => true,
+                SimTrigger::Edge(pindex, edge) => {
+                    let now = high(pindex);
+                    let hit = prev.is_some_and(|before| edge_matches(*edge, before, now));
+                    prev = Some(now);
+                    hit
+                }
+                SimTrigger::Pattern(pattern) => {
+                    let now = pattern.iter().all(|(pindex, level)| high(pindex) == *level);
+                    let hit = now && prev == Some(false);
+                    if sample >= pre {
+                        prev = Some(now);
+                    }
+                    hit
+                }
+                SimTrigger::Pulse(pindex, level, min) => {
+                    let now = high(pindex);
+                    let mut hit = false;
+                    if now == *level && prev == Some(!*level) {
+                        pulse_start = Some(last);
+                    } else if now != *level && prev == Some(*level) {
+                        hit = pulse_start
+                            .take()
+                            .is_some_and(|start: u32| last.wrapping_sub(start) > *min);
+                    }
+                    prev = Some(now);
+                    hit
+                }
+                SimTrigger::Threshold(pindex, value, edge) => {
+                    let now = levels[*pindex] >= *value;
+                    let hit = prev.is_some_and(|before| edge_matches(*edge, before, now));
+                    prev = Some(now);
+                    hit
+                }
+            };
+            // it can't fire until there are enough samples to come before it
+            if fired_at.is_none() && sample >= pre {
+                if hit {
+                    fired_at = Some(sample);
+                    triggered = true;
+                } else if began.elapsed() >= timeout {
+                    fired_at = Some(sample);
+                }
+            }
+            if fired_at.is_some_and(|at| sample - at >= count - pre - 1) {
+                break;
+            }
+            sample += 1;
+        }
+        let taken = sample + 1;
+        let oldest = taken % count;
+        let mut packed = vec![0u8; bits.div_ceil(8)];
+        for bit in 0..bits {
+            let slot = (oldest + bit / pin_count) % count;
+            if ring[slot * pin_count + bit % pin_count] {
+                packed[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        for (i, chunk) in packed.chunks(CAPTURE_CHUNK).enumerate() {
+            let mut payload = ((i * CAPTURE_CHUNK) as u16).to_le_bytes().to_vec();
+            payload.extend_from_slice(chunk);
+            self.send(port, seq, miso::CAPTURE_DATA, &payload)?;
+        }
+        let mut done = last.wrapping_sub(first).to_le_bytes().to_vec();
+        done.extend_from_slice(&(taken as u32).to_le_bytes());
+        done.push(triggered as u8);
+        self.send(port, seq, miso::CAPTURE_DONE, &done)?;
+        self.send(port, seq, miso::ACK, &[])
+    }
+
+    fn command(
+        &mut self,
+        port: &mut MemoryTransport,
+        seq: u8,
+        tp: u8,
+        args: &[u8],
+    ) -> std::io::Result<bool> {
+        // run one command from the host. false means drop back to waiting for a handshake
+        match (tp, args) {
+            (mosi::REQUEST_BOARD_PARAMETERS, host) if host.len() <= 1 => {
+                self.send(port, seq, miso::ACK, &[])?;
+                if !host.is_empty() {
+                    // a v2 host told us its version, so tell it ours. we serve every host, so MIN_HOST_PROTOCOL is 1
+                    let mut flags = 0;
+                    if self.pins.iter().any(|pin| pin.is_analog) {
+                        flags |= capability::ANALOG_IN;
+                    }
+                    if self.pins.iter().any(|pin| pin.has_pwm) {
+                        flags |= capability::PWM;
+                    }
+                    if self.pins.iter().any(|pin| pin.has_pullup) {
+                        flags |= capability::PULLUPS;
+                    }
+                    flags |= capability::TIMESTAMPS | capability::BUS_WRITES;
+                    let [flags_lo, flags_hi] = u16::to_le_bytes(flags);
+                    let [max_lo, max_hi] = u16::to_le_bytes(self.pins.len() as u16);
+                    let [capture_lo, capture_hi] = u16::to_le_bytes(self.capture_bytes);
+                    let [pattern_lo, pattern_hi] = u16::to_le_bytes(self.pattern.len() as u16);
+                    let payload = [
+                        self.version,
+                        1,
+                        flags_lo,
+                        flags_hi,
+                        max_lo,
+                        max_hi,
+                        capture_lo,
+                        capture_hi,
+                        pattern_lo,
+                        pattern_hi,
+                    ];
+                    self.send(port, seq, miso::CAPABILITIES, &payload)?;
+                }
+                self.send(port, seq, miso::SAMPLING_BOUNDS, &[0x10, 0x00])?;
+                for (i, pin) in self.pins.iter().enumerate() {
+                    let flags = if pin.is_analog { 0x80 } else { 0x00 }
+                        | if pin.has_pullup { 0x40 } else { 0x00 };
+                    let (tp, mut description) = if i < COMPACT_PINS as usize {
+                        (miso::PIN_DESCRIPTION, vec![i as u8 | flags])
+                    } else {
+                        (miso::PIN_DESCRIPTION_WIDE, vec![i as u8, flags])
+                    };
+                    description.extend_from_slice(pin.identifier.as_bytes());
+                    self.send(port, seq, tp, &description)?;
+                    if pin.has_pwm {
+                        self.send(port, seq, miso::PIN_PWM_CAPABLE, &[i as u8])?;
+                    }
+                }
+                self.send(port, seq, miso::BOARD_DESCRIPTION, self.name.as_bytes())?;
+            }
+            (mosi::SET_PIN_MODE_INPUT, [pindex]) => {
+                self.set_mode(port, seq, *pindex as usize, MODE_INPUT, false)?
+            }
+            (mosi::SET_PIN_MODE_OUTPUT, [pindex]) => {
+                self.set_mode(port, seq, *pindex as usize, MODE_OUTPUT, false)?
+            }
+            (mosi::SET_PIN_MODE_INPUT_PULLUP, [pindex]) => {
+                self.set_mode(port, seq, *pindex as usize, MODE_INPUT, true)?
+            }
+            (mosi::SET_DIGITAL_PIN_VALUE, [pindex, level]) => {
+                self.digital_write(*pindex as usize, *level != 0)
+            }
+            (mosi::SET_ANALOG_PIN_VALUE, [pindex, duty]) => {
+                let pindex = *pindex as usize;
+                if pindex < self.pins.len() && self.pins[pindex].has_pwm {
+                    self.send(port, seq, miso::ACK, &[])?;
+                    // analogWrite() flips the pin to an output by itself
+                    self.modes[pindex] = MODE_OUTPUT;
+                    self.drive[pindex] = (*duty as u32 * ADC_MAX as u32 / 255) as u16;
+                } else {
+                    self.send(port, seq, miso::ERROR, &[])?;
+                }
+            }
+            (mosi::SUBSCRIBE, [lo, hi]) => {
+                self.subsc_wavelength = u16::from_le_bytes([*lo, *hi]);
+                self.send(port, seq, miso::ACK, &[])?;
+            }
+            (mosi::SET_TIMESTAMPS, [on]) if self.version >= 2 => {
+                self.send(port, seq, miso::ACK, &[])?;
+                self.timestamps = *on != 0;
+            }
+            (mosi::SET_TIMESTAMPS, [_]) => self.send(port, seq, miso::ERROR, &[])?, // v1 has nowhere to put them
+            (mosi::CAPTURE, [p0, p1, p2, p3, c0, c1, pin_count, rest @ ..])
+                if self.version >= 2 =>
+            {
+                let period = u32::from_le_bytes([*p0, *p1, *p2, *p3]);
+                let count = u16::from_le_bytes([*c0, *c1]);
+                self.capture(port, seq, period, count, *pin_count as usize, rest)?
+            }
+            (mosi::PATTERN_DATA, [lo, hi, data @ ..]) if self.version >= 2 => {
+                self.stop_pattern(port)?;
+                let offset = u16::from_le_bytes([*lo, *hi]) as usize;
+                if offset == 0 {
+                    self.loaded = 0;
+                }
+                if offset == self.loaded && offset + data.len() <= self.pattern.len() {
+                    self.pattern[offset..offset + data.len()].copy_from_slice(data);
+                    self.loaded += data.len();
+                    self.send(port, seq, miso::ACK, &[])?;
+                } else {
+                    self.loaded = 0; // a chunk went missing, so nothing can play until the whole table comes again
+                    self.send(port, seq, miso::ERROR, &[])?;
+                }
+            }
+            (mosi::PLAY_PATTERN, [p0, p1, p2, p3, r0, r1, s0, s1, pin_count, pins @ ..])
+                if self.version >= 2 =>
+            {
+                self.stop_pattern(port)?;
+                let steps = u16::from_le_bytes([*s0, *s1]) as usize;
+                let pin_count = *pin_count as usize;
+                let valid = (1..=MAX_PLAY_PINS).contains(&pin_count)
+                    && pins.len() == pin_count
+                    && steps > 0
+                    && steps < u16::MAX as usize
+                    && (steps * pin_count).div_ceil(8) <= self.loaded
+                    && pins.iter().all(|pindex| {
+                        self.pins
+                            .get(*pindex as usize)
+                            .is_some_and(|pin| !pin.is_analog)
+                    });
+                if valid {
+                    self.playing = Some(SimPlayback {
+                        seq,
+                        pins: pins.iter().map(|pindex| *pindex as usize).collect(),
+                        steps,
+                        period: Duration::from_micros(
+                            u32::from_le_bytes([*p0, *p1, *p2, *p3]) as u64
+                        ),
+                        repeat: u16::from_le_bytes([*r0, *r1]),
+                        round: 0,
+                        step: 0,
+                        next: Instant::now(),
+                        shown: None,
+                    });
+                    self.send(port, seq, miso::ACK, &[])?;
+                    self.play_pattern(port)?;
+                } else {
+                    self.send(port, seq, miso::ERROR, &[])?;
+                }
+            }
+            (mosi::STOP_PATTERN, []) if self.version >= 2 => {
+                self.stop_pattern(port)?;
+                self.send(port, seq, miso::ACK, &[])?;
+            }
+            (mosi::SET_DIGITAL_PIN_VALUES, [count, rest @ ..]) if self.version >= 2 => {
+                // on the real thing these all land in the same instant, and here nothing reads the pins in between
+                let count = *count as usize;
+                let valid = (1..=MAX_BUS_PINS).contains(&count)
+                    && rest.len() == count + count.div_ceil(8)
+                    && rest[..count].iter().all(|pindex| {
+                        self.modes.get(*pindex as usize) == Some(&MODE_OUTPUT)
+                            && !self.pins[*pindex as usize].is_analog
+                    });
+                if valid {
+                    let (pins, levels) = rest.split_at(count);
+                    for (i, pindex) in pins.iter().enumerate() {
+                        self.digital_write(*pindex as usize, levels[i / 8] & (1 << (i % 8)) != 0);
+                    }
+                    self.send(port, seq, miso::ACK, &[])?;
+                } else {
+                    self.send(port, seq, miso::ERROR, &[])?;
+                }
+            }
+            (mosi::RUN_ONE_SAMPLE, []) => {
+                let micros = self.micros();
+                for (i, value) in self.read_all().into_iter().enumerate() {
+                    if self.modes[i] == MODE_INPUT {
+                        self.write_state(port, i, value, micros)?;
+                    }
+                }
+                self.send(port, seq, miso::ACK, &[])?;
+            }
+            (mosi::NEGOTIATE, [version]) => match (self.version, *version) {
+                (1, frame::VERSION) => {
+                    self.send(port, seq, miso::ACK, &[])?; // still unframed, so the host can tell
+                    self.version = frame::VERSION;
+                }
+                (2.., 1) => return Ok(false), // the host is starting over
+                _ => self.send(port, seq, miso::ERROR, &[])?,
+            },
+            (mosi::PLEASE_ESTABLISH, []) if self.version >= 2 => {
+                self.send(port, seq, miso::ACK, &[])?
+            }
+            _ => {
+                self.send(port, seq, miso::ERROR, &[])?; // send an error code and get out of here skoob
+                // v1 has no way of finding where the next command starts, so it gives up on the session; v2 just moves on
+                return Ok(self.version >= 2);
+            }
+        }
+        Ok(true)
+    }
+}
+
+enum SimTrigger {
+    // a capture trigger, as the board gets it. pins are pin table indexes, like everywhere else on the board
+    Immediate,
+    Edge(usize, u8),
+    Pattern(Vec<(usize, bool)>),
+    Pulse(usize, bool, u32),
+    Threshold(usize, u16, u8),
+}
+
+impl SimTrigger {
+    fn decode(bytes: &[u8]) -> Option<(Self, Duration, usize)> {
+        // the end of a CAPTURE command; see trigger.rs. also hands back the timeout and how many samples come before
+        let [kind, t0, t1, p0, p1, args @ ..] = bytes else {
+            return None;
+        };
+        let trigger = match (*kind, args) {
+            (0, []) => SimTrigger::Immediate,
+            (1, [pin, edge]) if *edge <= 2 => SimTrigger::Edge(*pin as usize, *edge),
+            (2, [n, pattern @ ..])
+                if (1..=MAX_PATTERN_PINS).contains(&(*n as usize))
+                    && pattern.len() == *n as usize * 2 =>
+            {
+                SimTrigger::Pattern(
+                    pattern
+                        .chunks(2)
+                        .map(|pair| (pair[0] as usize, pair[1] != 0))
+                        .collect(),
+                )
+            }
+            (3, [pin, level, m0, m1, m2, m3]) => SimTrigger::Pulse(
+                *pin as usize,
+                *level != 0,
+                u32::from_le_bytes([*m0, *m1, *m2, *m3]),
+            ),
+            (4, [pin, lo, hi, edge]) if *edge <= 2 => {
+                SimTrigger::Threshold(*pin as usize, u16::from_le_bytes([*lo, *hi]), *edge)
+            }
+            _ => return None,
+        };
+        let timeout = Duration::from_millis(u16::from_le_bytes([*t0, *t1]) as u64);
+        Some((trigger, timeout, u16::from_le_bytes([*p0, *p1]) as usize))
+    }
+}
+
+fn edge_matches(edge: u8, before: bool, after: bool) -> bool {
+    // 0 rising, 1 falling, 2 either
+    match edge {
+        0 => !before && after,
+        1 => before && !after,
+        _ => before != after,
+    }
+}
+
+fn arg_count(opcode: u8) -> usize {
+    // how many bytes follow each v1 opcode
+    match opcode {
+        mosi::SET_PIN_MODE_INPUT
+        | mosi::SET_PIN_MODE_OUTPUT
+        | mosi::SET_PIN_MODE_INPUT_PULLUP
+        | mosi::NEGOTIATE
+        | mosi::SET_TIMESTAMPS => 1,
+        mosi::SET_ANALOG_PIN_VALUE | mosi::SUBSCRIBE | mosi::SET_DIGITAL_PIN_VALUE => 2,
+        _ => 0,
+    }
+}
+
+#[derive(Default)]
+struct Nets {
+    // union-find over physical pin numbers
+    parents: HashMap<u8, u8>,
+}
+
+impl Nets {
+    fn find(&mut self, pin: u8) -> u8 {
+        let parent = *self.parents.entry(pin).or_insert(pin);
+        if parent == pin {
+            return pin;
+        }
+        let root = self.find(parent);
+        self.parents.insert(pin, root);
+        root
+    }
+
+    fn join(&mut self, a: u8, b: u8) {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a != b {
+            self.parents.insert(a, b);
+        }
+    }
+
+    fn roots(&mut self) -> Vec<u8> {
+        let pins: Vec<u8> = self.parents.keys().copied().collect();
+        let mut roots: Vec<u8> = pins.into_iter().map(|pin| self.find(pin)).collect();
+        roots.sort();
+        roots.dedup();
+        roots
+    }
+}
+
+fn poll_read(port: &mut MemoryTransport) -> std::io::Result<Option<u8>> {
+    // like Serial.available() + Serial.read(): None if nothing's come in yet
+    let mut buf = [0; 1];
+    match port.read(&mut buf) {
+        Ok(0) => Err(std::io::ErrorKind::UnexpectedEof.into()), // the host hung up
+        Ok(_) => Ok(Some(buf[0])),
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn block_read(port: &mut MemoryTransport) -> std::io::Result<u8> {
+    loop {
+        if let Some(byte) = poll_read(port)? {
+            return Ok(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, PinStatus};
+    use crate::connection::{Command, Connection, Event};
+    use crate::error::CircuitDojoError;
+    use crate::pattern::Pattern;
+
+    fn settle(board: &mut Board, pin: u8, want: bool) -> bool {
+        // keep updating until the pin reads want, or give up after a couple of seconds
+        let until = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < until {
+            board.update().unwrap();
+            let status = board.pins().find(|p| p.hw_id == pin).unwrap().status;
+            if matches!(status, PinStatus::DigitalInputting(level) if level == want) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    fn wired(wires: &[Wire], outputs: &[u8], inputs: &[u8]) -> Board {
+        // a simulated UNO with some wiring, and its pins set up. wires go between physical pins, and the UNO's pin
+        // table starts at physical 3, so hw_id n is physical n + 3
+        let mut sim = SimBoard::uno();
+        for wire in wires {
+            sim = sim.wire(*wire);
+        }
+        let mut board = Board::new(sim.spawn()).unwrap();
+        for pin in outputs {
+            board.set_output(*pin).unwrap();
+        }
+        for pin in inputs {
+            board.set_input(*pin).unwrap();
+        }
+        board.subscribe(10).unwrap(); // inputs only get reported with a subscription
+        board
+    }
+
+    #[test]
+    fn wires_carry_writes_to_inputs() {
+        // physical 4 drives 5 through a jumper and 6 through an inverter
+        let mut board = wired(&[Wire::Tie(4, 5), Wire::Inverter(4, 6)], &[1], &[2, 3]);
+        for level in [true, false, true] {
+            board.digital_write(1, level).unwrap();
+            assert!(
+                settle(&mut board, 2, level),
+                "tied input didn't follow {}",
+                level
+            );
+            assert!(
+                settle(&mut board, 3, !level),
+                "inverted input didn't follow {}",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn pull_up_reads_high_until_driven_low() {
+        let mut board = wired(&[Wire::PullUp(5), Wire::Tie(4, 5)], &[], &[2]);
+        assert!(
+            settle(&mut board, 2, true),
+            "nothing drives it, so it should read high"
+        );
+        board.set_output(1).unwrap();
+        board.digital_write(1, false).unwrap();
+        assert!(settle(&mut board, 2, false), "driven low over the pull-up");
+        board.digital_write(1, true).unwrap();
+        assert!(settle(&mut board, 2, true), "driven high");
+    }
+
+    #[test]
+    fn conflicting_drivers_read_low() {
+        // physical 4 and 6 both drive the net physical 5 is on
+        let mut board = wired(&[Wire::Tie(4, 5), Wire::Tie(5, 6)], &[1, 3], &[2]);
+        board.digital_write(1, true).unwrap();
+        board.digital_write(3, false).unwrap();
+        assert!(settle(&mut board, 2, false), "high against low");
+        board.digital_write(3, true).unwrap();
+        assert!(settle(&mut board, 2, true), "both high");
+        board.digital_write(1, false).unwrap();
+        assert!(settle(&mut board, 2, false), "low against high");
+    }
+
+    #[test]
+    fn ties_and_inverters_chain() {
+        // 4 -- 5 -> NOT -> 6 -- 7 -> NOT -> 8
+        let wires = [
+            Wire::Tie(4, 5),
+            Wire::Inverter(5, 6),
+            Wire::Tie(6, 7),
+            Wire::Inverter(7, 8),
+        ];
+        let mut board = wired(&wires, &[1], &[2, 4, 5]);
+        for level in [true, false, true] {
+            board.digital_write(1, level).unwrap();
+            assert!(
+                settle(&mut board, 2, level),
+                "tied input didn't follow {}",
+                level
+            );
+            assert!(
+                settle(&mut board, 4, !level),
+                "first inverter didn't follow {}",
+                level
+            );
+            assert!(
+                settle(&mut board, 5, level),
+                "second inverter didn't follow {}",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn analog_sources_read_as_wired() {
+        // A0 is physical 14, hw_id 11. digital pins see an analog source as high above half scale
+        let wires = [
+            Wire::Analog(14, 700),
+            Wire::Analog(7, 700),
+            Wire::Analog(8, 300),
+        ];
+        let mut board = wired(&wires, &[], &[11, 4, 5]);
+        let until = Instant::now() + Duration::from_secs(2);
+        while board.analog_read(11).unwrap() != Some(700) {
+            assert!(Instant::now() < until, "A0 didn't read 700");
+            board.update().unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(settle(&mut board, 4, true), "700 should read high");
+        assert!(settle(&mut board, 5, false), "300 should read low");
+    }
+
+    #[test]
+    fn capabilities_come_from_the_pin_table() {
+        let board = Board::new(SimBoard::uno().spawn()).unwrap();
+        let capabilities = board.capabilities();
+        assert_eq!(capabilities.max_pins, 17); // D3-D13 and A0-A5
+        assert!(capabilities.analog_in && capabilities.pwm && capabilities.pullups);
+        let board = Board::new(SimBoard::mega().spawn()).unwrap();
+        assert_eq!(board.capabilities().max_pins, 68); // D2-D53 and A0-A15
+        let digital = SimBoard::new("no frills", (2..=5).map(SimPin::digital).collect());
+        let board = Board::new(digital.spawn()).unwrap();
+        let capabilities = board.capabilities();
+        assert_eq!(capabilities.max_pins, 4);
+        assert!(!capabilities.analog_in && !capabilities.pwm);
+    }
+
+    fn levels(board: &mut Board, pin: u8, over: Duration) -> Vec<bool> {
+        // keep updating for a while, noting each level the pin goes to
+        let until = Instant::now() + over;
+        let mut levels = vec![];
+        while Instant::now() < until {
+            board.update().unwrap();
+            let status = board.pins().find(|p| p.hw_id == pin).unwrap().status;
+            if let PinStatus::DigitalInputting(level) = status
+                && levels.last() != Some(&level)
+            {
+                levels.push(level);
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        levels
+    }
+
+    fn pattern_board(on_board: bool) -> Board {
+        // patterns go out of physical 4 (hw_id 1), and a jumper carries them to physical 5 (hw_id 2)
+        let mut sim = SimBoard::uno().wire(Wire::Tie(4, 5));
+        if !on_board {
+            sim.pattern = vec![]; // no room for a table, so the host has to play them
+        }
+        let mut board = Board::new(sim.spawn()).unwrap();
+        assert_eq!(board.capabilities().pattern_bytes != 0, on_board);
+        board.set_output(1).unwrap().wait().unwrap();
+        board.set_input(2).unwrap().wait().unwrap();
+        board.subscribe(10).unwrap().wait().unwrap();
+        board
+    }
+
+    fn pattern_repeats_then_stops(on_board: bool) {
+        let mut board = pattern_board(on_board);
+        board
+            .play_pattern(&[1], &[1, 0, 1], Duration::from_millis(60), 2)
+            .unwrap()
+            .wait()
+            .unwrap();
+        // high, low, high, high, low, high, and then it stays there. the input might catch the low before it starts
+        let seen = levels(&mut board, 2, Duration::from_millis(800));
+        assert_eq!(
+            seen.strip_prefix(&[false]).unwrap_or(&seen),
+            [true, false, true, false, true]
+        );
+        let status = board.pins().find(|p| p.hw_id == 1).unwrap().status;
+        assert!(matches!(status, PinStatus::DigitalOutputting(true)));
+    }
+
+    fn pattern_stops_where_it_is(on_board: bool) {
+        let mut board = pattern_board(on_board);
+        board
+            .play_pattern(&[1], &[1, 0], Duration::from_millis(40), 0)
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(levels(&mut board, 2, Duration::from_millis(300)).len() >= 4);
+        board.stop_pattern().unwrap().wait().unwrap();
+        levels(&mut board, 2, Duration::from_millis(100)); // for the last of it to come in
+        let seen = levels(&mut board, 2, Duration::from_millis(300));
+        assert_eq!(seen.len(), 1, "still playing after it was stopped");
+        let status = board.pins().find(|p| p.hw_id == 1).unwrap().status;
+        assert!(matches!(status, PinStatus::DigitalOutputting(level) if level == seen[0]));
+    }
+
+    #[test]
+    fn pattern_repeats_then_stops_on_the_board() {
+        pattern_repeats_then_stops(true);
+    }
+
+    #[test]
+    fn pattern_repeats_then_stops_on_the_host() {
+        pattern_repeats_then_stops(false);
+    }
+
+    #[test]
+    fn pattern_stops_where_it_is_on_the_board() {
+        pattern_stops_where_it_is(true);
+    }
+
+    #[test]
+    fn pattern_stops_where_it_is_on_the_host() {
+        pattern_stops_where_it_is(false);
+    }
+
+    fn next(connection: &mut Connection, selector: impl Fn(&Event) -> bool + Copy) -> Event {
+        // event_wait, seeing out the read timeouts for a couple of seconds
+        let until = Instant::now() + Duration::from_secs(2);
+        loop {
+            match connection.event_wait(selector) {
+                Err(CircuitDojoError::TimedOut) if Instant::now() < until => {}
+                event => return event.unwrap(),
+            }
+        }
+    }
+
+    fn answered(connection: &mut Connection, command: Command) {
+        connection.write_command(command).unwrap();
+        let answer = next(connection, |e| {
+            matches!(e, Event::Ack(_) | Event::BoardError(_))
+        });
+        assert!(matches!(answer, Event::Ack(_)), "{:?}", answer);
+    }
+
+    #[test]
+    fn replaced_pattern_is_done_before_the_new_one() {
+        // the board stops a pattern as soon as the next one's table starts coming in, and its PATTERN_DONE has the
+        // SEQ of its own PLAY_PATTERN. SEQs come round every 255 commands, so line the new PLAY_PATTERN up on the same one
+        let mut connection = Connection::new(SimBoard::uno().spawn()).unwrap();
+        connection.begin().unwrap();
+        let old = Pattern {
+            pins: vec![1],
+            steps: vec![1, 1],
+            period_us: 1000,
+            repeat: 0,
+        };
+        let new = Pattern {
+            pins: vec![1],
+            steps: vec![0],
+            period_us: 1000,
+            repeat: 1,
+        };
+        answered(&mut connection, Command::SetPinModeOutput(1));
+        answered(&mut connection, Command::PatternData(0, old.packed()));
+        answered(&mut connection, Command::PlayPattern(old.clone()));
+        for _ in 0..253 {
+            answered(&mut connection, Command::Subscribe(0));
+        }
+        connection
+            .write_command(Command::PatternData(0, new.packed()))
+            .unwrap();
+        connection
+            .write_command(Command::PlayPattern(new.clone()))
+            .unwrap();
+        let done = |e: &Event| matches!(e, Event::PatternDone(..));
+        let Event::PatternDone(pattern, Some(_)) = next(&mut connection, done) else {
+            panic!("the old pattern had played a step");
+        };
+        assert_eq!(pattern.steps, old.steps);
+        let Event::PatternDone(pattern, step) = next(&mut connection, done) else {
+            unreachable!();
+        };
+        assert_eq!((pattern.steps, step), (new.steps, Some(0)));
+    }
+}
@@ -0,0 +1,163 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+*/
+// byte pipes that a Connection can talk over.
+// the board only ever needs something it can read bytes from, write bytes to, and put a timeout on,
+// so that's all a Transport is. serial ports are the normal case, but anything that moves bytes
+// (tcp sockets, ptys, in-memory pipes for tests and demos) works just as well.
+
+use crate::Result;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub trait Transport: Read + Write + Send {
+    // reads should give up with a TimedOut (or WouldBlock) error after this long without data
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()>;
+}
+
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        serialport::SerialPort::set_timeout(self.as_mut(), timeout)?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Transport for serialport::TTYPort {
+    // this is what you get from TTYPort::pair(), so ptys come for free
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        serialport::SerialPort::set_timeout(self, timeout)?;
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+}
+
+struct PipeState {
+    data: VecDeque<u8>,
+    closed: bool, // one of the ends was dropped
+}
+
+struct Pipe {
+    state: Mutex<PipeState>,
+    ready: Condvar,
+}
+
+impl Pipe {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(PipeState {
+                data: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        })
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+// one end of an in-memory, bidirectional byte pipe. whatever is written to one end can be read from the other.
+// dropping either end closes both directions: reads drain whatever's left and then hit EOF, writes fail.
+pub struct MemoryTransport {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+    timeout: Option<Duration>,
+}
+
+pub fn pipe() -> (MemoryTransport, MemoryTransport) {
+    let a = Pipe::new();
+    let b = Pipe::new();
+    (
+        MemoryTransport {
+            rx: a.clone(),
+            tx: b.clone(),
+            timeout: None,
+        },
+        MemoryTransport {
+            rx: b,
+            tx: a,
+            timeout: None,
+        },
+    )
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.rx.state.lock().unwrap();
+        while state.data.is_empty() {
+            if state.closed {
+                return Ok(0);
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(std::io::ErrorKind::TimedOut.into());
+                    }
+                    self.rx.ready.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.rx.ready.wait(state).unwrap(),
+            };
+        }
+        let count = buf.len().min(state.data.len());
+        for (slot, byte) in buf.iter_mut().zip(state.data.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.tx.state.lock().unwrap();
+        if state.closed {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        state.data.extend(buf);
+        self.tx.ready.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.timeout = Some(timeout);
+        Ok(())
+    }
+}
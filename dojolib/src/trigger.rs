@@ -0,0 +1,421 @@
+/*
+Copyright 2025 Tyler Clarke
+
+Redistribution and use in source and binary forms, with or without modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following disclaimer.
+
+2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the following disclaimer in the documentation and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS “AS IS” AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+// trigger conditions.
+// a trigger picks out the moment something interesting happens, so you can look at what led up to it and what came
+// after instead of hunting for it, same as on a bench scope. captures evaluate them on the board as they sample (see
+// Board::capture). for everything else Watch evaluates them on the host, over the samples a Recorder keeps.
+//
+// on the wire (the end of a CAPTURE command):
+//   KIND, TIMEOUT_MS (u16 LE), PRE (u16 LE), then for each KIND
+//   0 immediate:  nothing
+//   1 edge:       PIN, EDGE (0 rising, 1 falling, 2 either)
+//   2 pattern:    COUNT, then PIN, LEVEL for each
+//   3 pulse:      PIN, LEVEL, MIN_US (u32 LE)
+//   4 threshold:  PIN, VALUE (u16 LE), EDGE
+
+use crate::board::PinStatus;
+use crate::recorder::Sample;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const MAX_PATTERN_PINS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Either,
+}
+
+impl Edge {
+    fn matches(self, before: bool, after: bool) -> bool {
+        match self {
+            Edge::Rising => !before && after,
+            Edge::Falling => before && !after,
+            Edge::Either => before != after,
+        }
+    }
+
+    fn encode(self) -> u8 {
+        match self {
+            Edge::Rising => 0,
+            Edge::Falling => 1,
+            Edge::Either => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Immediate,                       // fires straight away
+    Edge(u8, Edge),                  // a digital pin changing level
+    Pattern(Vec<(u8, bool)>),        // the moment every one of these pins is at its level at once
+    PulseLonger(u8, bool, Duration), // the end of a pulse at this level that lasted longer than this
+    Threshold(u8, u16, Edge), // an analog reading crossing this value. rising means from below it to at or above it
+}
+
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub condition: Condition,
+    pub pre: usize, // samples to keep from before it fired
+    // samples to keep from after it fired. a capture doesn't use this: it keeps whatever its sample_count leaves over
+    pub post: usize,
+    // how long a capture waits for the condition before giving up and going ahead anyway (the board can't do anything
+    // else while it waits). at most 65 seconds
+    pub timeout: Duration,
+}
+
+impl Trigger {
+    pub fn new(condition: Condition) -> Self {
+        // change the rest with struct update syntax: Trigger { pre: 100, ..Trigger::rising(2) }
+        Self {
+            condition,
+            pre: 0,
+            post: 0,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn immediate() -> Self {
+        Self::new(Condition::Immediate)
+    }
+
+    pub fn rising(pin: u8) -> Self {
+        Self::new(Condition::Edge(pin, Edge::Rising))
+    }
+
+    pub fn falling(pin: u8) -> Self {
+        Self::new(Condition::Edge(pin, Edge::Falling))
+    }
+
+    pub fn pins(&self) -> Vec<u8> {
+        // every pin the condition looks at
+        match &self.condition {
+            Condition::Immediate => vec![],
+            Condition::Edge(pin, _)
+            | Condition::PulseLonger(pin, _, _)
+            | Condition::Threshold(pin, _, _) => vec![*pin],
+            Condition::Pattern(pins) => pins.iter().map(|(pin, _)| *pin).collect(),
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let (kind, args) = match &self.condition {
+            Condition::Immediate => (0, vec![]),
+            Condition::Edge(pin, edge) => (1, vec![*pin, edge.encode()]),
+            Condition::Pattern(pins) => {
+                let mut args = vec![pins.len() as u8];
+                for (pin, level) in pins {
+                    args.extend_from_slice(&[*pin, *level as u8]);
+                }
+                (2, args)
+            }
+            Condition::PulseLonger(pin, level, min) => {
+                let mut args = vec![*pin, *level as u8];
+                let min = min.as_micros().min(u32::MAX as u128) as u32;
+                args.extend_from_slice(&min.to_le_bytes());
+                (3, args)
+            }
+            Condition::Threshold(pin, value, edge) => {
+                let [lo, hi] = value.to_le_bytes();
+                (4, vec![*pin, lo, hi, edge.encode()])
+            }
+        };
+        let timeout = self.timeout.as_millis().min(u16::MAX as u128) as u16;
+        let mut payload = vec![kind];
+        payload.extend_from_slice(&timeout.to_le_bytes());
+        payload.extend_from_slice(&(self.pre.min(u16::MAX as usize) as u16).to_le_bytes());
+        payload.extend(args);
+        payload
+    }
+}
+
+fn digital(status: PinStatus) -> Option<bool> {
+    match status {
+        PinStatus::DigitalOutputting(level)
+        | PinStatus::DigitalInputting(level)
+        | PinStatus::DigitalPullupInputting(level) => Some(level),
+        _ => None,
+    }
+}
+
+fn analog(status: PinStatus) -> Option<u16> {
+    match status {
+        PinStatus::AnalogInputting(value) | PinStatus::AnalogOutputting(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub struct Watch {
+    // evaluates a trigger on the host. feed it samples in the order they were recorded
+    trigger: Trigger,
+    statuses: HashMap<u8, PinStatus>, // the latest status of every pin it's been fed
+    matched: bool,                    // Pattern: whether the pins matched as of the last sample
+    pulse_start: Option<Duration>,    // PulseLonger: when the pin went to the pulse's level
+    fired: bool,                      // Immediate: whether it's had its one sample yet
+}
+
+impl Watch {
+    pub fn new(trigger: Trigger) -> Self {
+        Self {
+            trigger,
+            statuses: HashMap::new(),
+            matched: false,
+            pulse_start: None,
+            fired: false,
+        }
+    }
+
+    pub fn trigger(&self) -> &Trigger {
+        &self.trigger
+    }
+
+    pub fn feed(&mut self, sample: &Sample) -> bool {
+        // true if this sample fires the trigger. an edge needs a sample from before it to be an edge, so a pin's
+        // first sample never fires Edge, PulseLonger or Threshold
+        let before = self.statuses.insert(sample.pin, sample.status);
+        match &self.trigger.condition {
+            Condition::Immediate => !std::mem::replace(&mut self.fired, true),
+            Condition::Edge(pin, edge) => {
+                sample.pin == *pin
+                    && matches!(
+                        (before.and_then(digital), digital(sample.status)),
+                        (Some(before), Some(after)) if edge.matches(before, after)
+                    )
+            }
+            Condition::Pattern(pins) => {
+                let matched = pins.iter().all(|(pin, level)| {
+                    self.statuses.get(pin).copied().and_then(digital) == Some(*level)
+                });
+                !std::mem::replace(&mut self.matched, matched) && matched
+            }
+            Condition::PulseLonger(pin, level, min) => {
+                if sample.pin != *pin {
+                    return false;
+                }
+                match (before.and_then(digital), digital(sample.status)) {
+                    (Some(before), Some(after)) if before != *level && after == *level => {
+                        self.pulse_start = Some(sample.host_time);
+                        false
+                    }
+                    (Some(before), Some(after)) if before == *level && after != *level => self
+                        .pulse_start
+                        .take()
+                        .is_some_and(|start| sample.host_time.saturating_sub(start) > *min),
+                    _ => false,
+                }
+            }
+            Condition::Threshold(pin, value, edge) => {
+                sample.pin == *pin
+                    && matches!(
+                        (before.and_then(analog), analog(sample.status)),
+                        (Some(before), Some(after)) if edge.matches(before >= *value, after >= *value)
+                    )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(trigger: &Trigger) -> [u8; 5] {
+        // KIND, TIMEOUT_MS, PRE
+        trigger.encode()[..5].try_into().unwrap()
+    }
+
+    #[test]
+    fn conditions_encode() {
+        let cases = [
+            (Condition::Immediate, vec![0]),
+            (Condition::Edge(3, Edge::Rising), vec![1, 3, 0]),
+            (Condition::Edge(3, Edge::Falling), vec![1, 3, 1]),
+            (Condition::Edge(3, Edge::Either), vec![1, 3, 2]),
+            (
+                Condition::Pattern(vec![(2, true), (5, false)]),
+                vec![2, 2, 2, 1, 5, 0],
+            ),
+            (
+                Condition::PulseLonger(4, true, Duration::from_micros(0x0102_0304)),
+                vec![3, 4, 1, 4, 3, 2, 1],
+            ),
+            (
+                // longer than a u32 of microseconds (about 71 minutes) is as long as the board can time
+                Condition::PulseLonger(4, false, Duration::from_secs(5000)),
+                vec![3, 4, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+            (
+                Condition::Threshold(11, 0x0203, Edge::Rising),
+                vec![4, 11, 3, 2, 0],
+            ),
+            (
+                Condition::Threshold(11, 0x0203, Edge::Falling),
+                vec![4, 11, 3, 2, 1],
+            ),
+            (
+                Condition::Threshold(11, 0x0203, Edge::Either),
+                vec![4, 11, 3, 2, 2],
+            ),
+        ];
+        for (condition, want) in cases {
+            let encoded = Trigger::new(condition.clone()).encode();
+            let (kind, args) = want.split_first().unwrap();
+            assert_eq!(encoded[0], *kind, "{:?}", condition);
+            assert_eq!(&encoded[5..], args, "{:?}", condition);
+        }
+    }
+
+    #[test]
+    fn timeout_encodes_in_milliseconds_up_to_65_seconds() {
+        let cases = [
+            (Duration::ZERO, [0, 0]),
+            (Duration::from_micros(999), [0, 0]),
+            (Duration::from_millis(1500), [0xDC, 0x05]),
+            (Duration::from_secs(10), [0x10, 0x27]),
+            (Duration::from_millis(65535), [0xFF, 0xFF]),
+            (Duration::from_millis(65536), [0xFF, 0xFF]),
+            (Duration::MAX, [0xFF, 0xFF]),
+        ];
+        for (timeout, want) in cases {
+            let trigger = Trigger {
+                timeout,
+                ..Trigger::immediate()
+            };
+            assert_eq!(header(&trigger)[1..3], want, "{:?}", timeout);
+        }
+    }
+
+    #[test]
+    fn pre_encodes_up_to_its_limit_and_post_not_at_all() {
+        for (pre, want) in [
+            (0, [0, 0]),
+            (1, [1, 0]),
+            (0x1234, [0x34, 0x12]),
+            (u16::MAX as usize, [0xFF, 0xFF]),
+            (u16::MAX as usize + 1, [0xFF, 0xFF]),
+            (usize::MAX, [0xFF, 0xFF]),
+        ] {
+            let trigger = Trigger {
+                pre,
+                ..Trigger::rising(2)
+            };
+            assert_eq!(header(&trigger)[3..5], want, "{}", pre);
+        }
+        // post is only for the host, so it doesn't change what goes to the board
+        let post = Trigger {
+            post: usize::MAX,
+            ..Trigger::rising(2)
+        };
+        assert_eq!(post.encode(), Trigger::rising(2).encode());
+    }
+
+    fn feed(watch: &mut Watch, at_ms: u64, pin: u8, status: PinStatus) -> bool {
+        watch.feed(&Sample {
+            host_time: Duration::from_millis(at_ms),
+            board_time: None,
+            pin,
+            status,
+        })
+    }
+
+    fn level(high: bool) -> PinStatus {
+        PinStatus::DigitalInputting(high)
+    }
+
+    #[test]
+    fn watch_fires_immediate_once() {
+        let mut watch = Watch::new(Trigger::immediate());
+        assert!(feed(&mut watch, 0, 2, level(false)));
+        assert!(!feed(&mut watch, 1, 2, level(true)));
+    }
+
+    #[test]
+    fn watch_fires_on_edges() {
+        for (edge, fires) in [
+            (Edge::Rising, [false, true, false, true]),
+            (Edge::Falling, [false, false, true, false]),
+            (Edge::Either, [false, true, true, true]),
+        ] {
+            let mut watch = Watch::new(Trigger::new(Condition::Edge(2, edge)));
+            // the first sample has nothing to be an edge from
+            assert!(!feed(&mut watch, 0, 2, level(false)), "{:?}", edge);
+            // other pins don't count
+            assert!(!feed(&mut watch, 2, 3, level(false)), "{:?}", edge);
+            assert!(!feed(&mut watch, 3, 3, level(true)), "{:?}", edge);
+            let seen = [false, true, false, true].map(|high| feed(&mut watch, 4, 2, level(high)));
+            // false to false isn't an edge at all
+            assert_eq!(seen, fires, "{:?}", edge);
+        }
+    }
+
+    #[test]
+    fn watch_fires_when_a_pattern_starts_matching() {
+        let mut watch = Watch::new(Trigger::new(Condition::Pattern(vec![
+            (2, true),
+            (3, false),
+        ])));
+        assert!(
+            !feed(&mut watch, 0, 2, level(true)),
+            "pin 3 hasn't been seen yet"
+        );
+        assert!(feed(&mut watch, 1, 3, level(false)));
+        assert!(
+            !feed(&mut watch, 2, 4, level(true)),
+            "still matching isn't starting to"
+        );
+        assert!(!feed(&mut watch, 3, 3, level(true)));
+        assert!(feed(&mut watch, 4, 3, level(false)));
+    }
+
+    #[test]
+    fn watch_fires_at_the_end_of_a_long_enough_pulse() {
+        let mut watch = Watch::new(Trigger::new(Condition::PulseLonger(
+            2,
+            true,
+            Duration::from_millis(10),
+        )));
+        assert!(!feed(&mut watch, 0, 2, level(false)));
+        assert!(!feed(&mut watch, 1, 2, level(true)));
+        assert!(
+            !feed(&mut watch, 11, 2, level(false)),
+            "exactly 10ms isn't longer"
+        );
+        assert!(!feed(&mut watch, 20, 2, level(true)));
+        assert!(!feed(&mut watch, 25, 3, level(false)), "another pin");
+        assert!(feed(&mut watch, 31, 2, level(false)));
+        // a pulse that was already going when the watch started has no known start
+        let mut watch = Watch::new(Trigger::new(Condition::PulseLonger(
+            2,
+            true,
+            Duration::from_millis(10),
+        )));
+        assert!(!feed(&mut watch, 0, 2, level(true)));
+        assert!(!feed(&mut watch, 50, 2, level(false)));
+    }
+
+    #[test]
+    fn watch_fires_on_threshold_crossings() {
+        let analog = PinStatus::AnalogInputting;
+        for (edge, fires) in [
+            (Edge::Rising, [true, false, false, false]),
+            (Edge::Falling, [false, false, true, false]),
+            (Edge::Either, [true, false, true, false]),
+        ] {
+            let mut watch = Watch::new(Trigger::new(Condition::Threshold(11, 512, edge)));
+            assert!(!feed(&mut watch, 0, 11, analog(100)), "{:?}", edge);
+            // to exactly the value counts as reaching it, and staying on one side doesn't cross
+            let seen = [512, 1023, 511, 0].map(|value| feed(&mut watch, 1, 11, analog(value)));
+            assert_eq!(seen, fires, "{:?}", edge);
+        }
+    }
+}